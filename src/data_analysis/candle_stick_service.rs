@@ -1,149 +1,604 @@
+use std::collections::BTreeMap;
+
 use crate::database_clients::data_web_client::DataTradeModel;
-use crate::data_analysis::finnhub_data_row::FinnhubDataRow;
+use crate::database_clients::wire_format::{BarState, LastTrade, SymbolState};
+use crate::data_analysis::trade::Trade;
+use crate::data_analysis::price::Price;
+use crate::data_analysis::historical_bar::HistoricalBar;
+use crate::data_analysis::clock::Clock;
+use crate::data_analysis::market_hours::MarketHours;
+use crate::values_store::app_config::{BarPolicy, LateTradePolicy};
+
+const DAY_SECONDS: usize = 86_400;
+const MAX_CATCH_UP_MS: i64 = 60 * 60 * 1000;
+
+/*
+    Open and close are the first and last trade by exchange time, trades with the same
+    timestamp keep their order of arrival
+*/
+struct CandleBar {
+    open_price: Price,
+    open_time: i64,
+    close_price: Price,
+    close_time: i64,
+
+    total_volume: i64,
+    total_trades: i64,
+    total_price: i128, //sum of price * size
+    min_price: Price,
+    max_price: Price,
+}
+
+impl CandleBar {
+    fn new() -> Self {
+        CandleBar {
+            open_price: Price::ZERO,
+            open_time: i64::MAX,
+            close_price: Price::ZERO,
+            close_time: i64::MIN,
+
+            total_volume: 0,
+            total_trades: 0,
+            total_price: 0,
+            min_price: Price::MAX,
+            max_price: Price::MIN,
+        }
+    }
+
+    fn add_trade(&mut self, trade: &Trade) {
+        let timestamp = trade.timestamp_millis();
+
+        if timestamp < self.open_time {
+            self.open_price = trade.price;
+            self.open_time = timestamp;
+        }
+
+        if timestamp >= self.close_time {
+            self.close_price = trade.price;
+            self.close_time = timestamp;
+        }
+
+        self.total_volume += trade.size;
+        self.total_trades += 1;
+        self.total_price += trade.price.notional(trade.size);
+        self.min_price = self.min_price.min(trade.price);
+        self.max_price = self.max_price.max(trade.price);
+    }
+
+    /*
+        Vendor bars without a vwap are weighted at their close
+    */
+    fn add_bar(&mut self, bar: &HistoricalBar) {
+        if bar.timestamp < self.open_time {
+            self.open_price = bar.open_price;
+            self.open_time = bar.timestamp;
+        }
+
+        if bar.timestamp >= self.close_time {
+            self.close_price = bar.close_price;
+            self.close_time = bar.timestamp;
+        }
 
+        self.total_volume += bar.volume;
+        self.total_trades += bar.num_of_trades;
+        self.total_price += bar.vwap.unwrap_or(bar.close_price).notional(bar.volume);
+        self.min_price = self.min_price.min(bar.low_price);
+        self.max_price = self.max_price.max(bar.high_price);
+    }
+
+    /*
+        Bars of quotes only have no volume, their vwap falls back to the close
+    */
+    fn data_trade(&self, bar_start: i64, stock_name: &str, interval_seconds: usize, price_decimals: u32) -> DataTradeModel {
+        DataTradeModel {
+            timestamp: bar_start,
+            stock_name: stock_name.to_string(),
+            stock_interval: interval_seconds,
+            price_decimals,
+            open_price: self.open_price,
+            high_price: self.max_price,
+            low_price: self.min_price,
+            close_price: self.close_price,
+            vwap: match Price::average(self.total_price, self.total_volume) {
+                Some(v) => v,
+                None => self.close_price,
+            },
+            volume_moved: self.total_volume,
+            num_of_trades: self.total_trades,
+        }
+    }
+}
+
+/*
+    Bars of one interval, keyed by the exchange timestamp of the trades. A bar covers
+    [bar_start, bar_start + interval) on epoch aligned boundaries and is sent once the
+    clock passed its end plus the grace period.
+*/
 pub struct CandleStickGraph {
-    open: f64,
+    last_close: Price,
 
-    pub total_volume: i64,
-    pub total_trades: i64,
-    pub total_price: i64,
-    pub min_price: i64,
-    pub max_price: i64,
-    pub timestamp: i64,
+    open_bars: BTreeMap<i64, CandleBar>,
+    next_bar: Option<i64>,
+    last_bar: Option<(i64, CandleBar)>,
+    amended_bars: Vec<DataTradeModel>,
 
     stock_name: String,
-    current_interval: usize,
+    price_decimals: u32,
     interval_seconds: usize,
+    bar_policy: BarPolicy,
 }
 
 impl CandleStickGraph {
-    pub fn new(interval_seconds: usize, stock_name: String) -> Self {
+    pub fn new(interval_seconds: usize, stock_name: String, price_decimals: u32, bar_policy: BarPolicy) -> Self {
         CandleStickGraph {
-            open: 0.0,
-            total_trades: 0,
-            total_volume: 0,
-            total_price: 0,
-            min_price: std::i64::MAX,
-            max_price: std::i64::MIN,
-            timestamp: 0,
-            
-            stock_name: stock_name,
-            current_interval: 0,
-            interval_seconds: interval_seconds,
+            last_close: Price::ZERO,
+
+            open_bars: BTreeMap::new(),
+            next_bar: None,
+            last_bar: None,
+            amended_bars: Vec::new(),
+
+            stock_name,
+            price_decimals,
+            interval_seconds,
+            bar_policy,
+        }
+    }
+
+    pub fn add_trade(&mut self, trade: &Trade) {
+        let bar_start = self.bar_start(trade.timestamp_millis());
+
+        match self.next_bar {
+            Some(v) if bar_start < v => self.add_late_trade(bar_start, trade),
+            _ => self.open_bars.entry(bar_start).or_insert_with(CandleBar::new).add_trade(trade),
         }
     }
 
-    pub fn add_trade_candle(&mut self, trade: &CandleStickGraph) -> Option<DataTradeModel> {
-        self.total_volume += trade.total_volume;
-        self.total_trades += trade.total_trades;
-        self.total_price += trade.total_price;
-        self.min_price = self.min_price.min(trade.min_price);
-        self.max_price = self.max_price.max(trade.max_price);
-        self.timestamp = self.timestamp.max(trade.timestamp);
+    /*
+        Bars of the history are merged into every interval they fit into. Shorter intervals
+        only take the close, so their flat bars continue from it until live trades arrive.
+    */
+    pub fn add_bar(&mut self, bar: &HistoricalBar) {
+        let bar_start = self.bar_start(bar.timestamp);
 
-        self.current_interval += 1;
+        if self.next_bar.is_some_and(|v| bar_start < v) {
+            return;
+        }
 
-        match self.current_interval >= self.interval_seconds {
-            true => Some(self.get_data_trade()),
-            false => None,
+        match (self.interval_seconds.is_multiple_of(bar.interval_seconds), self.next_bar) {
+            (true, _) => self.open_bars.entry(bar_start).or_insert_with(CandleBar::new).add_bar(bar),
+            (false, None) => self.last_close = bar.close_price,
+            (false, Some(_)) => (),
         }
     }
 
-    pub fn add_trade_main(&mut self, trade: &FinnhubDataRow) {
-        self.total_volume += trade.v;
-        self.total_trades += 1;
-        self.total_price += trade.p * trade.v;
-        self.min_price = self.min_price.min(trade.p);
-        self.max_price = self.max_price.max(trade.p);
-        self.timestamp = self.timestamp.max(trade.t);
-    }
-
-    fn get_data_trade(&mut self) -> DataTradeModel {
-        let data_trade_model = match self.total_volume {
-            0 => {
-                self.timestamp += self.interval_seconds as i64 * 1000;
-                DataTradeModel {
-                    timestamp: self.timestamp,
-                    stock_name: self.stock_name.clone(),
-                    stock_interval: self.interval_seconds,
-                    avg_price: self.open,
-                    avg_price_open: self.open,
-                    min_price: self.open,
-                    max_price: self.open,
-                    volume_moved: 0,
-                    num_of_trades: 0,
-                }
-            },
-            _ => DataTradeModel {
-                timestamp: self.timestamp,
-                stock_name: self.stock_name.clone(),
-                stock_interval: self.interval_seconds,
-                avg_price: self.total_price as f64 / self.total_volume as f64,
-                avg_price_open: match self.open {
-                    0.0 => self.total_price as f64 / self.total_volume as f64,
-                    _ => self.open,
-                },
-                min_price: self.min_price as f64,
-                max_price: self.max_price as f64,
-                volume_moved: self.total_volume,
-                num_of_trades: self.total_trades,
-            }
+    /*
+        Sends every bar that ended at least grace_ms before the clock's time, intervals without
+        trades are sent as flat bars at the previous close once a price is known. Gaps further
+        back than MAX_CATCH_UP_MS are skipped instead of filled, so a stale first trade does not
+        produce a flat bar for every interval up to now.
+    */
+    pub fn get_trades(&mut self, clock: &dyn Clock) -> Vec<DataTradeModel> {
+        let mut list_of_trades:Vec<DataTradeModel> = self.amended_bars.drain(..).collect();
+        let now_ms = clock.now_millis();
+        let interval_ms = self.interval_ms();
+
+        let mut bar_start = match self.next_bar.or_else(|| self.open_bars.keys().next().copied()) {
+            Some(v) => v,
+            None => return list_of_trades,
         };
 
-        self.reset();
+        let earliest_flat_bar = self.bar_start(now_ms - MAX_CATCH_UP_MS);
+
+        while bar_start + interval_ms + self.bar_policy.grace_ms <= now_ms {
+            if bar_start < earliest_flat_bar && !self.open_bars.contains_key(&bar_start) {
+                // open bars never start before next_bar, the first one is the next real bar
+                bar_start = match self.open_bars.keys().next() {
+                    Some(v) => earliest_flat_bar.min(*v),
+                    None => earliest_flat_bar,
+                };
+                self.next_bar = Some(bar_start);
+
+                continue;
+            }
+
+            match self.open_bars.remove(&bar_start) {
+                Some(bar) => list_of_trades.push(self.close_bar(bar_start, bar)),
+                None if self.last_close != Price::ZERO => list_of_trades.push(self.flat_bar(bar_start)),
+                None => (),
+            }
+
+            bar_start += interval_ms;
+            self.next_bar = Some(bar_start);
+        }
+
+        list_of_trades
+    }
+
+    /*
+        The newest bar that was not sent yet
+    */
+    pub fn current_bar(&self) -> Option<DataTradeModel> {
+        self.open_bars.iter().next_back().map(|(bar_start, bar)| self.data_trade(*bar_start, bar))
+    }
+
+    fn add_late_trade(&mut self, bar_start: i64, trade: &Trade) {
+        if self.bar_policy.late_trades == LateTradePolicy::Drop {
+            return;
+        }
+
+        if let Some((last_start, mut bar)) = self.last_bar.take() {
+            if last_start == bar_start {
+                bar.add_trade(trade);
+
+                self.last_close = bar.close_price;
+                self.amended_bars.push(self.data_trade(bar_start, &bar));
+            }
+
+            self.last_bar = Some((last_start, bar));
+        }
+    }
+
+    fn close_bar(&mut self, bar_start: i64, bar: CandleBar) -> DataTradeModel {
+        let data_trade_model = self.data_trade(bar_start, &bar);
+
+        self.last_close = bar.close_price;
+
+        if self.bar_policy.late_trades == LateTradePolicy::Amend {
+            self.last_bar = Some((bar_start, bar));
+        }
 
         data_trade_model
     }
 
-    fn reset(&mut self) {
-        self.open = match self.total_volume {
-            0 => self.open,
-            _ => self.total_price as f64 / self.total_volume as f64,
-        };
-        self.total_price = 0;
-        self.total_volume = 0;
-        self.total_trades = 0;
-        self.min_price = std::i64::MAX;
-        self.max_price = std::i64::MIN;
-        self.current_interval = 0;
+    fn flat_bar(&mut self, bar_start: i64) -> DataTradeModel {
+        self.last_bar = None;
+
+        DataTradeModel {
+            timestamp: bar_start,
+            stock_name: self.stock_name.clone(),
+            stock_interval: self.interval_seconds,
+            price_decimals: self.price_decimals,
+            open_price: self.last_close,
+            high_price: self.last_close,
+            low_price: self.last_close,
+            close_price: self.last_close,
+            vwap: self.last_close,
+            volume_moved: 0,
+            num_of_trades: 0,
+        }
+    }
+
+    fn data_trade(&self, bar_start: i64, bar: &CandleBar) -> DataTradeModel {
+        bar.data_trade(bar_start, &self.stock_name, self.interval_seconds, self.price_decimals)
+    }
+
+    fn bar_start(&self, timestamp_ms: i64) -> i64 {
+        timestamp_ms - timestamp_ms.rem_euclid(self.interval_ms())
+    }
+
+    fn interval_ms(&self) -> i64 {
+        self.interval_seconds as i64 * 1000
     }
 }
 
 
+/*
+    The bars of every interval of one symbol. The bar of the exchange's day and the last
+    trade are only kept for the query server, they are never sent.
+*/
 pub struct CandleStickService {
-    cs_graph_main: CandleStickGraph,
     cs_graphs: Vec<CandleStickGraph>,
+    market_hours: MarketHours,
+    day_bar: Option<(i64, CandleBar)>,
+    last_trade: Option<Trade>,
+    last_update_ms: Option<i64>,
+
+    stock_name: String,
+    price_decimals: u32,
 }
 
 impl CandleStickService {
-    pub fn new(stock_name: String) -> Self {
+    pub fn new(stock_name: String, price_decimals: u32, intervals: &[usize], bar_policy: BarPolicy, market_hours: MarketHours) -> Self {
+        let mut cs_graphs:Vec<CandleStickGraph> = vec![CandleStickGraph::new(1, stock_name.clone(), price_decimals, bar_policy)];
+
+        for interval_seconds in intervals.iter() {
+            cs_graphs.push(CandleStickGraph::new(*interval_seconds, stock_name.clone(), price_decimals, bar_policy));
+        }
+
         CandleStickService {
-            cs_graph_main: CandleStickGraph::new(1, stock_name.clone()),
-            cs_graphs: vec![
-                CandleStickGraph::new(10, stock_name.clone()),
-                CandleStickGraph::new(60, stock_name.clone()),
-                CandleStickGraph::new(300, stock_name.clone()),
-                CandleStickGraph::new(600, stock_name.clone())
-            ],
+            cs_graphs,
+            market_hours,
+            day_bar: None,
+            last_trade: None,
+            last_update_ms: None,
+
+            stock_name,
+            price_decimals,
+        }
+    }
+
+    pub fn add_trade(&mut self, trade: &Trade) {
+        for cs_graph in self.cs_graphs.iter_mut() {
+            cs_graph.add_trade(trade);
+        }
+
+        if let Some(bar) = self.day_bar_for(trade.timestamp_millis()) {
+            bar.add_trade(trade);
+        }
+
+        if self.last_trade.as_ref().is_none_or(|v| trade.timestamp >= v.timestamp) {
+            self.last_trade = Some(trade.clone());
+        }
+    }
+
+    pub fn add_bar(&mut self, bar: &HistoricalBar) {
+        for cs_graph in self.cs_graphs.iter_mut() {
+            cs_graph.add_bar(bar);
+        }
+
+        // bars of a day or longer are aligned to UTC and do not fit into the exchange's day
+        if bar.interval_seconds < DAY_SECONDS {
+            if let Some(day_bar) = self.day_bar_for(bar.timestamp) {
+                day_bar.add_bar(bar);
+            }
+        }
+    }
+
+    /*
+        A new day replaces the bar of the previous one, trades of days before are dropped
+    */
+    fn day_bar_for(&mut self, timestamp_ms: i64) -> Option<&mut CandleBar> {
+        let day_start = self.market_hours.day_start(timestamp_ms);
+
+        match &self.day_bar {
+            Some((v, _)) if day_start < *v => return None,
+            Some((v, _)) if day_start == *v => (),
+            _ => self.day_bar = Some((day_start, CandleBar::new())),
+        };
+
+        self.day_bar.as_mut().map(|(_, bar)| bar)
+    }
+
+    /*
+        Drops the bar of the day once the exchange's day it belongs to is over
+    */
+    fn close_past_days(&mut self, now_ms: i64) {
+        if self.day_bar.as_ref().is_some_and(|(v, _)| *v < self.market_hours.day_start(now_ms)) {
+            self.day_bar = None;
         }
     }
 
-    pub fn add_trade(&mut self, trade: &FinnhubDataRow) {
-        self.cs_graph_main.add_trade_main(trade);
+    /*
+        Time the last trade arrived, by the clock of the analyser
+    */
+    pub fn set_last_update(&mut self, now_ms: i64) {
+        self.last_update_ms = Some(now_ms);
     }
 
-    pub fn get_trades(&mut self) -> Vec<DataTradeModel> {
+    pub fn get_trades(&mut self, clock: &dyn Clock) -> Vec<DataTradeModel> {
         let mut list_of_trades:Vec<DataTradeModel> = Vec::new();
 
         for cs_graph in self.cs_graphs.iter_mut() {
-            match cs_graph.add_trade_candle(&self.cs_graph_main) {
-                Some(v) => list_of_trades.push(v),
-                None => (),
-            };
+            list_of_trades.append(&mut cs_graph.get_trades(clock));
         }
 
-        list_of_trades.push(self.cs_graph_main.get_data_trade());
+        self.close_past_days(clock.now_millis());
 
         list_of_trades
     }
-}
\ No newline at end of file
+
+    pub fn state(&self, now_ms: i64) -> SymbolState {
+        SymbolState {
+            symbol: self.stock_name.clone(),
+            last_trade: self.last_trade.as_ref().map(|v| LastTrade::new(v, self.price_decimals)),
+            today: self.day_bar.as_ref().map(|(bar_start, bar)| BarState::new(&bar.data_trade(*bar_start, &self.stock_name, DAY_SECONDS, self.price_decimals))),
+            current_bars: self.cs_graphs.iter().filter_map(|v| v.current_bar()).map(|v| BarState::new(&v)).collect(),
+            ms_since_update: self.last_update_ms.map(|v| now_ms - v),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::values_store::app_config::{BarPolicy, LateTradePolicy};
+    use crate::data_analysis::candle_stick_service::CandleStickService;
+    use crate::data_analysis::price::Price;
+    use crate::data_analysis::trade::Trade;
+    use crate::data_analysis::historical_bar::HistoricalBar;
+    use crate::data_analysis::clock::ManualClock;
+    use crate::data_analysis::market_hours::MarketHours;
+
+    // 2024-09-06 15:27:00 UTC
+    const MINUTE: i64 = 1_725_636_420_000;
+
+
+    fn service(late_trades: LateTradePolicy) -> CandleStickService {
+        CandleStickService::new("AAPL".to_string(), 2, &[60], BarPolicy { grace_ms: 500, late_trades }, MarketHours::us_equities())
+    }
+
+    #[test]
+    fn bars_follow_exchange_time() {
+        let mut candle_stick_service = service(LateTradePolicy::Drop);
+
+        candle_stick_service.add_trade(&Trade::fixture("AAPL", 10.0, MINUTE - 200).with_size(1));
+        candle_stick_service.add_trade(&Trade::fixture("AAPL", 11.0, MINUTE + 59_900).with_size(1));
+        candle_stick_service.add_trade(&Trade::fixture("AAPL", 12.0, MINUTE + 60_100).with_size(1));
+
+        assert!(candle_stick_service.get_trades(&ManualClock::new(MINUTE + 400)).iter().all(|v| v.stock_interval == 1));
+
+        let list_of_trades = candle_stick_service.get_trades(&ManualClock::new(MINUTE + 60_500));
+        let minute_bars: Vec<_> = list_of_trades.iter().filter(|v| v.stock_interval == 60).collect();
+
+        assert_eq!(minute_bars.len(), 2);
+        assert_eq!(minute_bars[0].timestamp, MINUTE - 60_000);
+        assert_eq!(minute_bars[0].high_price.to_string(), "10");
+        assert_eq!(minute_bars[1].timestamp, MINUTE);
+        assert_eq!(minute_bars[1].high_price.to_string(), "11");
+        assert_eq!(minute_bars[1].volume_moved, 1);
+    }
+
+    #[test]
+    fn late_trades_are_dropped() {
+        let mut candle_stick_service = service(LateTradePolicy::Drop);
+
+        candle_stick_service.add_trade(&Trade::fixture("AAPL", 10.0, MINUTE + 1_000).with_size(1));
+        let _ = candle_stick_service.get_trades(&ManualClock::new(MINUTE + 2_500));
+
+        candle_stick_service.add_trade(&Trade::fixture("AAPL", 20.0, MINUTE + 1_500).with_size(5));
+
+        let list_of_trades = candle_stick_service.get_trades(&ManualClock::new(MINUTE + 60_500));
+        let second_bars: Vec<_> = list_of_trades.iter().filter(|v| v.stock_interval == 1).collect();
+        let minute_bars: Vec<_> = list_of_trades.iter().filter(|v| v.stock_interval == 60).collect();
+
+        assert!(second_bars.iter().all(|v| v.volume_moved == 0));
+        assert_eq!(second_bars.len(), 58);
+        assert_eq!(minute_bars[0].volume_moved, 6);
+    }
+
+    #[test]
+    fn late_trades_amend_the_last_bar() {
+        let mut candle_stick_service = service(LateTradePolicy::Amend);
+
+        candle_stick_service.add_trade(&Trade::fixture("AAPL", 10.0, MINUTE + 1_000).with_size(1));
+        let _ = candle_stick_service.get_trades(&ManualClock::new(MINUTE + 2_500));
+
+        candle_stick_service.add_trade(&Trade::fixture("AAPL", 20.0, MINUTE + 1_500).with_size(3));
+        candle_stick_service.add_trade(&Trade::fixture("AAPL", 30.0, MINUTE + 200).with_size(3));
+
+        let list_of_trades = candle_stick_service.get_trades(&ManualClock::new(MINUTE + 2_600));
+
+        assert_eq!(list_of_trades.len(), 1);
+        assert_eq!(list_of_trades[0].timestamp, MINUTE + 1_000);
+        assert_eq!(list_of_trades[0].volume_moved, 4);
+        assert_eq!(list_of_trades[0].vwap.to_string(), "17.5");
+        assert_eq!(list_of_trades[0].high_price.to_string(), "20");
+        assert_eq!(list_of_trades[0].close_price.to_string(), "20");
+    }
+
+    #[test]
+    fn bars_carry_open_high_low_close() {
+        let mut candle_stick_service = service(LateTradePolicy::Drop);
+
+        candle_stick_service.add_trade(&Trade::fixture("AAPL", 10.5, MINUTE + 2_000));
+        candle_stick_service.add_trade(&Trade::fixture("AAPL", 10.0, MINUTE + 1_000));
+        candle_stick_service.add_trade(&Trade::fixture("AAPL", 12.0, MINUTE + 30_000));
+        candle_stick_service.add_trade(&Trade::fixture("AAPL", 9.0, MINUTE + 40_000));
+        candle_stick_service.add_trade(&Trade::fixture("AAPL", 11.0, MINUTE + 50_000).with_size(200));
+        candle_stick_service.add_trade(&Trade::fixture("AAPL", 11.25, MINUTE + 50_000));
+
+        let list_of_trades = candle_stick_service.get_trades(&ManualClock::new(MINUTE + 60_500));
+        let minute_bar = list_of_trades.iter().find(|v| v.stock_interval == 60).unwrap();
+
+        assert_eq!(minute_bar.timestamp, MINUTE);
+        assert_eq!(minute_bar.open_price.to_string(), "10");
+        assert_eq!(minute_bar.high_price.to_string(), "12");
+        assert_eq!(minute_bar.low_price.to_string(), "9");
+        assert_eq!(minute_bar.close_price.to_string(), "11.25");
+        assert_eq!(minute_bar.vwap.to_string(), "10.67857143");
+        assert_eq!(minute_bar.volume_moved, 700);
+        assert_eq!(minute_bar.num_of_trades, 6);
+
+        let flat_bar = list_of_trades.iter().rfind(|v| v.stock_interval == 1).unwrap();
+
+        assert_eq!(flat_bar.volume_moved, 0);
+        assert_eq!(flat_bar.open_price.to_string(), "11.25");
+        assert_eq!(flat_bar.low_price.to_string(), "11.25");
+    }
+
+    #[test]
+    fn history_seeds_the_bars() {
+        let mut candle_stick_service = service(LateTradePolicy::Drop);
+
+        candle_stick_service.add_bar(&HistoricalBar {
+            symbol: "AAPL".to_string(),
+            timestamp: MINUTE - 60_000,
+            interval_seconds: 60,
+            open_price: Price::from_f64(10.0).unwrap(),
+            high_price: Price::from_f64(11.0).unwrap(),
+            low_price: Price::from_f64(9.5).unwrap(),
+            close_price: Price::from_f64(10.5).unwrap(),
+            vwap: None,
+            volume: 300,
+            num_of_trades: 0,
+        });
+        candle_stick_service.add_trade(&Trade::fixture("AAPL", 12.0, MINUTE + 61_000));
+
+        let list_of_trades = candle_stick_service.get_trades(&ManualClock::new(MINUTE + 60_500));
+        let minute_bars: Vec<_> = list_of_trades.iter().filter(|v| v.stock_interval == 60).collect();
+
+        assert_eq!(minute_bars.len(), 2);
+        assert_eq!(minute_bars[0].open_price.to_string(), "10");
+        assert_eq!(minute_bars[0].close_price.to_string(), "10.5");
+        assert_eq!(minute_bars[0].vwap.to_string(), "10.5");
+        assert_eq!(minute_bars[0].volume_moved, 300);
+        assert_eq!(minute_bars[1].volume_moved, 0);
+        assert_eq!(minute_bars[1].open_price.to_string(), "10.5");
+        assert!(list_of_trades.iter().all(|v| v.stock_interval == 60));
+    }
+
+    #[test]
+    fn state_shows_the_bars_in_progress() {
+        let mut candle_stick_service = service(LateTradePolicy::Drop);
+
+        assert_eq!(candle_stick_service.state(MINUTE).last_trade, None);
+
+        candle_stick_service.add_trade(&Trade::fixture("AAPL", 10.0, MINUTE - 86_400_000).with_size(2));
+        candle_stick_service.add_trade(&Trade::fixture("AAPL", 11.0, MINUTE + 1_000).with_size(1));
+        candle_stick_service.add_trade(&Trade::fixture("AAPL", 12.0, MINUTE + 30_000).with_size(3));
+        candle_stick_service.set_last_update(MINUTE + 30_100);
+        let _ = candle_stick_service.get_trades(&ManualClock::new(MINUTE + 2_000));
+
+        let symbol_state = candle_stick_service.state(MINUTE + 31_000);
+        let today = symbol_state.today.unwrap();
+
+        assert_eq!(symbol_state.last_trade.unwrap().price, "12.00");
+        assert_eq!(symbol_state.ms_since_update, Some(900));
+        // the day starts at midnight in New York
+        assert_eq!((today.interval_seconds, today.start), (86_400, 1_725_595_200_000));
+        assert_eq!((today.open.as_str(), today.high.as_str(), today.volume), ("11.00", "12.00", 4));
+        assert_eq!(symbol_state.current_bars.iter().map(|v| (v.interval_seconds, v.start, v.trades)).collect::<Vec<_>>(), vec![
+            (1, MINUTE + 30_000, 1),
+            (60, MINUTE, 2),
+        ]);
+    }
+
+    #[test]
+    fn today_ends_with_the_exchange_day() {
+        let mut candle_stick_service = service(LateTradePolicy::Drop);
+
+        // 20:30 in New York is still the same day
+        candle_stick_service.add_trade(&Trade::fixture("AAPL", 10.0, MINUTE).with_size(2));
+        candle_stick_service.add_trade(&Trade::fixture("AAPL", 11.0, 1_725_669_000_000).with_size(1));
+        let _ = candle_stick_service.get_trades(&ManualClock::new(1_725_669_060_000));
+
+        assert_eq!(candle_stick_service.state(1_725_669_060_000).today.unwrap().volume, 3);
+
+        // Saturday 2024-09-07 04:00:00 UTC, midnight in New York
+        let _ = candle_stick_service.get_trades(&ManualClock::new(1_725_681_600_000));
+
+        assert_eq!(candle_stick_service.state(1_725_681_600_000).today, None);
+    }
+
+    #[test]
+    fn stale_trades_are_not_caught_up_interval_by_interval() {
+        let mut candle_stick_service = service(LateTradePolicy::Drop);
+
+        candle_stick_service.add_trade(&Trade::fixture("AAPL", 10.0, MINUTE - 86_400_000).with_size(1));
+        candle_stick_service.add_trade(&Trade::fixture("AAPL", 11.0, MINUTE - 30_000).with_size(1));
+
+        let list_of_trades = candle_stick_service.get_trades(&ManualClock::new(MINUTE + 600));
+        let minute_bars: Vec<_> = list_of_trades.iter().filter(|v| v.stock_interval == 60).collect();
+
+        assert_eq!(list_of_trades.iter().filter(|v| v.stock_interval == 1).count(), 3_600 + 1);
+        assert_eq!(minute_bars.len(), 60 + 1);
+        assert_eq!((minute_bars[0].timestamp, minute_bars[0].volume_moved), (MINUTE - 86_400_000, 1));
+        assert_eq!(minute_bars[1].timestamp, MINUTE - 3_600_000);
+        assert_eq!((minute_bars[60].timestamp, minute_bars[60].high_price.to_string().as_str()), (MINUTE - 60_000, "11"));
+
+        let mut candle_stick_service = service(LateTradePolicy::Drop);
+        candle_stick_service.add_trade(&Trade::fixture("AAPL", 10.0, 0).with_size(1));
+
+        assert_eq!(candle_stick_service.get_trades(&ManualClock::new(MINUTE + 600)).len(), 1 + 3_600 + 1 + 60);
+    }
+}
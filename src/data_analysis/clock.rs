@@ -0,0 +1,67 @@
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH, Duration};
+
+/*
+    Source of the time bars are closed by, milliseconds since the epoch
+*/
+pub trait Clock: Send + Sync {
+    fn now_millis(&self) -> i64;
+
+    fn sleep_until(&self, target_ms: i64);
+}
+
+pub fn now_millis() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).expect("Time Went backwards").as_millis() as i64
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_millis(&self) -> i64 {
+        now_millis()
+    }
+
+    fn sleep_until(&self, target_ms: i64) {
+        let now_ms = now_millis();
+
+        if target_ms > now_ms {
+            thread::sleep(Duration::from_millis((target_ms - now_ms) as u64));
+        }
+    }
+}
+
+/*
+    Only moves when told to, sleeping jumps straight to the target. Clones share the time,
+    so a replay or a test can step the clock the analyser reads.
+*/
+#[derive(Clone)]
+pub struct ManualClock {
+    now_ms: Arc<Mutex<i64>>,
+}
+
+impl ManualClock {
+    pub fn new(now_ms: i64) -> Self {
+        ManualClock { now_ms: Arc::new(Mutex::new(now_ms)) }
+    }
+
+    pub fn set(&self, now_ms: i64) {
+        *self.now_ms.lock().unwrap() = now_ms;
+    }
+
+    pub fn advance(&self, by_ms: i64) {
+        *self.now_ms.lock().unwrap() += by_ms;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_millis(&self) -> i64 {
+        *self.now_ms.lock().unwrap()
+    }
+
+    fn sleep_until(&self, target_ms: i64) {
+        let mut now_ms = self.now_ms.lock().unwrap();
+
+        *now_ms = (*now_ms).max(target_ms);
+    }
+}
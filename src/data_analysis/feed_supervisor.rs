@@ -0,0 +1,253 @@
+use std::thread;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::values_store::app_config::ProviderKind;
+use crate::database_clients::trade_web_server::TradeWebServer;
+use crate::data_analysis::market_hours::MarketHours;
+use crate::data_analysis::clock::now_millis;
+
+#[derive(Debug, PartialEq)]
+pub struct FailoverEvent {
+    pub symbol: String,
+    pub from: ProviderKind,
+    pub to: ProviderKind,
+    pub timestamp: i64,
+}
+
+struct SymbolFeed {
+    active: ProviderKind,
+    // start of the stall timer or time of the last primary trade
+    last_primary: i64,
+    // first primary trade after the last gap
+    primary_since: Option<i64>,
+}
+
+/*
+    Watches the primary provider per symbol. If it delivers no valid trade for a symbol for
+    longer than stall_threshold_ms during market hours, the symbol is switched to the backup
+    provider. It is switched back once the primary delivered trades of the symbol without
+    such a gap for stall_threshold_ms again.
+*/
+pub struct FeedSupervisor {
+    primary: ProviderKind,
+    backup: ProviderKind,
+    stall_threshold_ms: i64,
+    market_hours: MarketHours,
+    symbols: HashMap<String, SymbolFeed>,
+}
+
+impl FeedSupervisor {
+    pub fn new(primary: ProviderKind, backup: ProviderKind, stall_threshold_ms: i64, market_hours: MarketHours) -> Self {
+        FeedSupervisor {
+            primary,
+            backup,
+            stall_threshold_ms,
+            market_hours,
+            symbols: HashMap::new(),
+        }
+    }
+
+    /*
+        Starts the stall timer of a symbol even if the primary never reports it
+    */
+    pub fn watch(&mut self, symbol: &str, now_ms: i64) {
+        let primary = self.primary;
+
+        self.symbols.entry(symbol.to_string()).or_insert(SymbolFeed {
+            active: primary,
+            last_primary: now_ms,
+            primary_since: None,
+        });
+    }
+
+    pub fn record_trade(&mut self, source: ProviderKind, symbol: &str, now_ms: i64) {
+        self.watch(symbol, now_ms);
+
+        if source != self.primary {
+            return;
+        }
+
+        let feed = self.symbols.get_mut(symbol).unwrap();
+
+        if feed.primary_since.is_none() || now_ms - feed.last_primary > self.stall_threshold_ms {
+            feed.primary_since = Some(now_ms);
+        }
+
+        feed.last_primary = feed.last_primary.max(now_ms);
+    }
+
+    /*
+        Trades of providers which are neither primary nor backup are never filtered
+    */
+    pub fn is_active(&self, source: ProviderKind, symbol: &str) -> bool {
+        if source != self.primary && source != self.backup {
+            return true;
+        }
+
+        match self.symbols.get(symbol) {
+            Some(feed) => feed.active == source,
+            None => source == self.primary,
+        }
+    }
+
+    pub fn check(&mut self, now_ms: i64) -> Vec<FailoverEvent> {
+        let market_open = self.market_hours.is_open(now_ms);
+        let mut list_of_events: Vec<FailoverEvent> = Vec::new();
+
+        for (symbol, feed) in self.symbols.iter_mut() {
+            let stalled = now_ms - feed.last_primary > self.stall_threshold_ms;
+
+            let target = if feed.active == self.primary {
+                match stalled && market_open {
+                    true => self.backup,
+                    false => continue,
+                }
+            } else {
+                match !stalled && feed.primary_since.is_some_and(|v| now_ms - v >= self.stall_threshold_ms) {
+                    true => self.primary,
+                    false => continue,
+                }
+            };
+
+            list_of_events.push(FailoverEvent {
+                symbol: symbol.clone(),
+                from: feed.active,
+                to: target,
+                timestamp: now_ms,
+            });
+
+            feed.active = target;
+        }
+
+        list_of_events.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        list_of_events
+    }
+}
+
+pub fn start_supervisor(feed_supervisor: Arc<Mutex<FeedSupervisor>>, mut trade_web_server: TradeWebServer) {
+    thread::spawn(move || {
+        loop {
+            thread::sleep(Duration::from_millis(1000));
+
+            let list_of_events = feed_supervisor.lock().unwrap().check(now_millis());
+
+            for event in list_of_events.into_iter() {
+                println!("Failover for {} from {} to {}", event.symbol, event.from, event.to);
+                trade_web_server.add_failover(&event);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::values_store::app_config::ProviderKind;
+    use crate::data_analysis::market_hours::MarketHours;
+    use crate::data_analysis::feed_supervisor::{FeedSupervisor, FailoverEvent};
+
+    // Friday 2024-09-06 15:00:00 UTC, market is open
+    const OPEN: i64 = 1_725_634_800_000;
+    // Saturday 2024-09-07 15:00:00 UTC
+    const CLOSED: i64 = 1_725_721_200_000;
+
+    fn supervisor() -> FeedSupervisor {
+        FeedSupervisor::new(ProviderKind::Finnhub, ProviderKind::Alpaca, 30_000, MarketHours::us_equities())
+    }
+
+    #[test]
+    fn switches_to_backup_and_back() {
+        let mut feed_supervisor = supervisor();
+
+        feed_supervisor.record_trade(ProviderKind::Finnhub, "AAPL", OPEN);
+
+        assert!(feed_supervisor.check(OPEN + 30_000).is_empty());
+        assert!(feed_supervisor.is_active(ProviderKind::Finnhub, "AAPL"));
+        assert!(!feed_supervisor.is_active(ProviderKind::Alpaca, "AAPL"));
+
+        assert_eq!(feed_supervisor.check(OPEN + 30_001), vec![FailoverEvent {
+            symbol: "AAPL".to_string(),
+            from: ProviderKind::Finnhub,
+            to: ProviderKind::Alpaca,
+            timestamp: OPEN + 30_001,
+        }]);
+        assert!(feed_supervisor.is_active(ProviderKind::Alpaca, "AAPL"));
+        assert!(!feed_supervisor.is_active(ProviderKind::Finnhub, "AAPL"));
+
+        assert!(feed_supervisor.check(OPEN + 60_000).is_empty());
+
+        for i in 0..4 {
+            feed_supervisor.record_trade(ProviderKind::Finnhub, "AAPL", OPEN + 61_000 + i * 10_000);
+        }
+
+        let list_of_events = feed_supervisor.check(OPEN + 91_000);
+
+        assert_eq!(list_of_events.len(), 1);
+        assert_eq!(list_of_events[0].to, ProviderKind::Finnhub);
+        assert_eq!(list_of_events[0].from, ProviderKind::Alpaca);
+        assert_eq!(list_of_events[0].timestamp, OPEN + 91_000);
+    }
+
+    #[test]
+    fn primary_has_to_stay_healthy_before_switching_back() {
+        let mut feed_supervisor = supervisor();
+
+        feed_supervisor.watch("AAPL", OPEN);
+
+        assert_eq!(feed_supervisor.check(OPEN + 30_001).len(), 1);
+
+        // a single trade after the stall is not enough
+        feed_supervisor.record_trade(ProviderKind::Finnhub, "AAPL", OPEN + 40_000);
+
+        assert!(feed_supervisor.check(OPEN + 41_000).is_empty());
+        assert!(feed_supervisor.check(OPEN + 70_001).is_empty());
+        assert!(feed_supervisor.is_active(ProviderKind::Alpaca, "AAPL"));
+
+        // a gap restarts the healthy period
+        feed_supervisor.record_trade(ProviderKind::Finnhub, "AAPL", OPEN + 80_000);
+        feed_supervisor.record_trade(ProviderKind::Finnhub, "AAPL", OPEN + 100_000);
+
+        assert!(feed_supervisor.check(OPEN + 105_000).is_empty());
+        assert_eq!(feed_supervisor.check(OPEN + 110_000).len(), 1);
+        assert!(feed_supervisor.is_active(ProviderKind::Finnhub, "AAPL"));
+    }
+
+    #[test]
+    fn only_the_quiet_symbol_fails_over() {
+        let mut feed_supervisor = supervisor();
+
+        feed_supervisor.watch("AAPL", OPEN);
+        feed_supervisor.watch("TSM", OPEN);
+
+        for i in 1..4 {
+            feed_supervisor.record_trade(ProviderKind::Finnhub, "AAPL", OPEN + i * 10_000);
+        }
+
+        assert_eq!(feed_supervisor.check(OPEN + 30_001), vec![FailoverEvent {
+            symbol: "TSM".to_string(),
+            from: ProviderKind::Finnhub,
+            to: ProviderKind::Alpaca,
+            timestamp: OPEN + 30_001,
+        }]);
+        assert!(feed_supervisor.is_active(ProviderKind::Finnhub, "AAPL"));
+        assert!(feed_supervisor.is_active(ProviderKind::Alpaca, "TSM"));
+        assert!(!feed_supervisor.is_active(ProviderKind::Finnhub, "TSM"));
+
+        feed_supervisor.record_trade(ProviderKind::Finnhub, "AAPL", OPEN + 40_000);
+
+        assert!(feed_supervisor.check(OPEN + 45_000).is_empty());
+        assert!(feed_supervisor.is_active(ProviderKind::Finnhub, "AAPL"));
+    }
+
+    #[test]
+    fn stays_on_primary_outside_market_hours() {
+        let mut feed_supervisor = supervisor();
+
+        feed_supervisor.watch("AAPL", CLOSED);
+
+        assert!(feed_supervisor.check(CLOSED + 3_600_000).is_empty());
+        assert!(feed_supervisor.is_active(ProviderKind::Finnhub, "AAPL"));
+        assert!(feed_supervisor.is_active(ProviderKind::Twelve, "AAPL"));
+    }
+}
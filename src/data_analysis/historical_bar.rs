@@ -0,0 +1,34 @@
+use crate::data_analysis::price::Price;
+
+/*
+    Bar of a vendor's history endpoint, timestamp is the start of the bar in epoch millis.
+    Used to seed the candles after a restart.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoricalBar {
+    pub symbol: String,
+    pub timestamp: i64,
+    pub interval_seconds: usize,
+
+    pub open_price: Price,
+    pub high_price: Price,
+    pub low_price: Price,
+    pub close_price: Price,
+    pub vwap: Option<Price>,
+
+    pub volume: i64,
+    pub num_of_trades: i64,
+}
+
+impl HistoricalBar {
+    pub fn end_millis(&self) -> i64 {
+        self.timestamp + self.interval_seconds as i64 * 1000
+    }
+
+    pub fn is_valid(&self) -> bool {
+        !self.symbol.is_empty()
+            && self.interval_seconds > 0
+            && self.volume >= 0
+            && self.low_price <= self.high_price
+    }
+}
@@ -0,0 +1,83 @@
+use chrono::{DateTime, Datelike, NaiveTime, TimeZone, Weekday};
+use chrono_tz::{Tz, America::New_York};
+
+/*
+    Regular trading session of an exchange. Holidays are not taken into account.
+*/
+#[derive(Clone, Copy)]
+pub struct MarketHours {
+    timezone: Tz,
+    open: NaiveTime,
+    close: NaiveTime,
+}
+
+impl MarketHours {
+    pub fn new(timezone: Tz, open: NaiveTime, close: NaiveTime) -> Self {
+        MarketHours { timezone, open, close }
+    }
+
+    pub fn us_equities() -> Self {
+        MarketHours::new(
+            New_York,
+            NaiveTime::from_hms_opt(9, 30, 0).unwrap(),
+            NaiveTime::from_hms_opt(16, 0, 0).unwrap(),
+        )
+    }
+
+    pub fn is_open(&self, timestamp_ms: i64) -> bool {
+        let local_time: DateTime<Tz> = match self.timezone.timestamp_millis_opt(timestamp_ms).single() {
+            Some(v) => v,
+            None => return false,
+        };
+
+        match local_time.weekday() {
+            Weekday::Sat | Weekday::Sun => false,
+            _ => local_time.time() >= self.open && local_time.time() < self.close,
+        }
+    }
+
+    /*
+        Midnight of the exchange's day the timestamp falls on, so trades before the open and
+        after the close count to the session of that day
+    */
+    pub fn day_start(&self, timestamp_ms: i64) -> i64 {
+        let local_time: DateTime<Tz> = match self.timezone.timestamp_millis_opt(timestamp_ms).single() {
+            Some(v) => v,
+            None => return timestamp_ms,
+        };
+
+        match self.timezone.from_local_datetime(&local_time.date_naive().and_time(NaiveTime::MIN)).earliest() {
+            Some(v) => v.timestamp_millis(),
+            None => timestamp_ms,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data_analysis::market_hours::MarketHours;
+
+    #[test]
+    fn us_equities_session() {
+        let market_hours = MarketHours::us_equities();
+
+        // Friday 2024-09-06 15:27:56 UTC, 11:27 in New York
+        assert!(market_hours.is_open(1_725_636_476_000));
+        // Friday 2024-09-06 13:00:00 UTC, 09:00 in New York
+        assert!(!market_hours.is_open(1_725_627_600_000));
+        // Saturday 2024-09-07 15:00:00 UTC
+        assert!(!market_hours.is_open(1_725_721_200_000));
+    }
+
+    #[test]
+    fn days_start_at_midnight_in_new_york() {
+        let market_hours = MarketHours::us_equities();
+
+        // Friday 2024-09-06 04:00:00 UTC, midnight in New York
+        assert_eq!(market_hours.day_start(1_725_636_476_000), 1_725_595_200_000);
+        // Saturday 2024-09-07 00:30:00 UTC, 20:30 on Friday in New York
+        assert_eq!(market_hours.day_start(1_725_669_000_000), 1_725_595_200_000);
+        // Monday 2024-11-04 17:00:00 UTC, midnight is at 05:00 UTC after daylight saving time
+        assert_eq!(market_hours.day_start(1_730_739_600_000), 1_730_696_400_000);
+    }
+}
@@ -1,3 +1,9 @@
 pub mod stock_analysis;
-pub mod finnhub_data_row;
-pub mod candle_stick_service;
\ No newline at end of file
+pub mod trade;
+pub mod historical_bar;
+pub mod price;
+pub mod candle_stick_service;
+pub mod trade_consolidator;
+pub mod feed_supervisor;
+pub mod market_hours;
+pub mod clock;
\ No newline at end of file
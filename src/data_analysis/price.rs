@@ -0,0 +1,234 @@
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, de::Error};
+use serde_json::Number;
+
+pub const MAX_DECIMALS: u32 = 8;
+
+const UNITS_PER_WHOLE: i64 = 100_000_000;
+
+/*
+    Fixed point price in units of 10^-8. Vendor prices are rounded into this representation
+    once while parsing, everything after that is integer arithmetic.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Price(i64);
+
+impl Price {
+    pub const ZERO: Price = Price(0);
+    pub const MIN: Price = Price(i64::MIN);
+    pub const MAX: Price = Price(i64::MAX);
+
+    pub fn from_units(units: i64) -> Self {
+        Price(units)
+    }
+
+    pub fn units(&self) -> i64 {
+        self.0
+    }
+
+    /*
+        Rounds to the nearest 10^-8, which is exact for every decimal literal with
+        up to 15 significant digits. Only tests write prices as floats, vendor
+        prices go through parse
+    */
+    #[cfg(test)]
+    pub fn from_f64(value: f64) -> Option<Self> {
+        let units = (value * UNITS_PER_WHOLE as f64).round();
+
+        match units.is_finite() && units.abs() < i64::MAX as f64 {
+            true => Some(Price(units as i64)),
+            false => None,
+        }
+    }
+
+    /*
+        Exact decimal text like "156.97", "-0.0012" or "1.5e-3", rounded half away from zero
+        to 10^-8 without going through a float
+    */
+    pub fn parse(raw_value: &str) -> Option<Self> {
+        let (mantissa, exponent) = match raw_value.find(['e', 'E']) {
+            Some(i) => (&raw_value[..i], raw_value[i + 1..].parse::<i64>().ok()?),
+            None => (raw_value, 0),
+        };
+
+        let (negative, digits) = match mantissa.strip_prefix('-') {
+            Some(v) => (true, v),
+            None => (false, mantissa.strip_prefix('+').unwrap_or(mantissa)),
+        };
+
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+
+        if whole.is_empty() && fraction.is_empty() || !format!("{}{}", whole, fraction).bytes().all(|v| v.is_ascii_digit()) {
+            return None;
+        }
+
+        let all_digits = format!("{}{}", whole, fraction);
+        let all_digits = all_digits.trim_start_matches('0').as_bytes();
+
+        // value = all_digits * 10^(scale - MAX_DECIMALS)
+        let scale = exponent.checked_add(MAX_DECIMALS as i64 - fraction.len() as i64)?;
+        let kept = (all_digits.len() as i64 + scale.min(0)).max(0) as usize;
+
+        let mut units: i128 = 0;
+
+        for digit in all_digits[..kept].iter() {
+            units = units.checked_mul(10)?.checked_add((digit - b'0') as i128)?;
+        }
+
+        if scale > 0 && units != 0 {
+            units = units.checked_mul(10_i128.checked_pow(u32::try_from(scale).ok()?)?)?;
+        }
+
+        if scale < 0 && all_digits.len() as i64 + scale >= 0 && all_digits.get(kept).is_some_and(|v| *v >= b'5') {
+            units += 1;
+        }
+
+        let units = i64::try_from(units).ok()?;
+
+        Some(Price(if negative { -units } else { units }))
+    }
+
+    /*
+        Rounds half away from zero to the given number of decimals
+    */
+    pub fn round_to(&self, decimals: u32) -> Price {
+        let step = 10_i64.pow(MAX_DECIMALS - decimals.min(MAX_DECIMALS));
+        let half = step / 2;
+
+        let rounded = match self.0 >= 0 {
+            true => (self.0.saturating_add(half) / step) * step,
+            false => -((-self.0).saturating_add(half) / step) * step,
+        };
+
+        Price::from_units(rounded)
+    }
+
+    /*
+        Volume weighted average of a notional (sum of units * size), rounded to the nearest unit
+    */
+    pub fn average(notional: i128, volume: i64) -> Option<Price> {
+        if volume == 0 {
+            return None;
+        }
+
+        let volume = volume as i128;
+        let rounded = match notional >= 0 {
+            true => (notional + volume / 2) / volume,
+            false => (notional - volume / 2) / volume,
+        };
+
+        Some(Price::from_units(rounded as i64))
+    }
+
+    pub fn notional(&self, size: i64) -> i128 {
+        self.units() as i128 * size as i128
+    }
+
+    /*
+        Decimal representation with exactly the given number of decimals
+    */
+    pub fn format(&self, decimals: u32) -> String {
+        let decimals = decimals.min(MAX_DECIMALS);
+        let rounded = self.round_to(decimals).0;
+
+        let sign = if rounded < 0 { "-" } else { "" };
+        let whole = rounded.unsigned_abs() / UNITS_PER_WHOLE as u64;
+        let fraction = rounded.unsigned_abs() % UNITS_PER_WHOLE as u64;
+
+        match decimals {
+            0 => format!("{}{}", sign, whole),
+            _ => format!("{}{}.{}", sign, whole, &format!("{:08}", fraction)[..decimals as usize]),
+        }
+    }
+}
+
+/*
+    Shortest exact decimal representation
+*/
+impl fmt::Display for Price {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let formatted = self.format(MAX_DECIMALS);
+        let trimmed = formatted.trim_end_matches('0').trim_end_matches('.');
+
+        write!(f, "{}", trimmed)
+    }
+}
+
+/*
+    Vendors send prices as json numbers, they are parsed from the text of the number
+*/
+impl<'de> Deserialize<'de> for Price {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Number::deserialize(deserializer)?;
+
+        Price::parse(&value.to_string()).ok_or_else(|| D::Error::custom(format!("price out of range: {}", value)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data_analysis::price::Price;
+
+    fn price(value: f64) -> Price {
+        Price::from_f64(value).unwrap()
+    }
+
+    #[test]
+    fn float_prices_are_exact() {
+        assert_eq!(price(156.97).units(), 15_697_000_000);
+        assert_eq!(price(0.0012).units(), 120_000);
+        assert_eq!(price(0.0012).to_string(), "0.0012");
+        assert_eq!(price(1.10974).to_string(), "1.10974");
+        assert_eq!(price(56912.01).to_string(), "56912.01");
+        assert_eq!(price(-0.5).to_string(), "-0.5");
+        assert_eq!(price(12.0).to_string(), "12");
+        assert!(Price::from_f64(f64::NAN).is_none());
+        assert!(Price::from_f64(1e300).is_none());
+    }
+
+    #[test]
+    fn rounding_and_formatting() {
+        let value = price(0.123456);
+
+        assert_eq!(value.round_to(4), price(0.1235));
+        assert_eq!(value.format(2), "0.12");
+        assert_eq!(value.format(8), "0.12345600");
+        assert_eq!(price(-2.005).format(2), "-2.01");
+        assert_eq!(price(7.5).format(0), "8");
+    }
+
+    #[test]
+    fn decimal_text_is_exact() {
+        assert_eq!(Price::parse("156.97").unwrap().units(), 15_697_000_000);
+        assert_eq!(Price::parse("0.30000000000000004").unwrap(), Price::parse("0.3").unwrap());
+        assert_eq!(Price::parse("-0.000000015").unwrap().units(), -2);
+        assert_eq!(Price::parse("0.000000004").unwrap().units(), 0);
+        assert_eq!(Price::parse("1.5e-3").unwrap().to_string(), "0.0015");
+        assert_eq!(Price::parse("12E2").unwrap().to_string(), "1200");
+        assert_eq!(Price::parse("92233720368.54775807").unwrap(), Price::MAX);
+        assert!(Price::parse("92233720368.54775808").is_none());
+        assert!(Price::parse("1e400").is_none());
+        assert!(Price::parse("1.2.3").is_none());
+        assert!(Price::parse("-").is_none());
+    }
+
+    #[test]
+    fn json_prices_skip_floats() {
+        let list_of_prices: Vec<Price> = serde_json::from_str("[0.1, 0.2, 0.3, 1.0000000049, 7]").unwrap();
+
+        // 0.1 + 0.2 is 0.30000000000000004 as f64
+        assert_eq!(list_of_prices[0].units() + list_of_prices[1].units(), list_of_prices[2].units());
+        assert_eq!(list_of_prices[3].units(), 100_000_000);
+        assert_eq!(list_of_prices[4].to_string(), "7");
+        assert!(serde_json::from_str::<Price>("\"1.5\"").is_err());
+    }
+
+    #[test]
+    fn volume_weighted_average() {
+        let notional = price(10.00).notional(100) + price(10.01).notional(300);
+
+        assert_eq!(Price::average(notional, 400).unwrap().to_string(), "10.0075");
+        assert!(Price::average(notional, 0).is_none());
+    }
+}
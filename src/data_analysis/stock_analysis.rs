@@ -1,130 +1,418 @@
 use std::collections::{HashMap};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, RwLock, Mutex};
+use std::sync::mpsc::Sender;
 use std::thread;
-use std::ops::AddAssign;
-use std::time::{SystemTime, UNIX_EPOCH, Duration};
 
 use crate::data_parsers::finnhub_parser::parse_finnhub_data;
 use crate::data_parsers::eodhd_parser::parse_eodhd_data;
 use crate::data_parsers::alpaca_parser::parse_alpaca_data;
 use crate::data_parsers::twelve_parser::parse_twelve_data;
+use crate::data_parsers::tiingo_parser::TiingoUpdate;
+use crate::data_parsers::parse_error::ParseError;
 
 use crate::database_clients::data_web_client::DataWebClient;
 use crate::database_clients::data_web_client::DataTradeModel;
 use crate::database_clients::trade_web_server::TradeWebServer;
+use crate::database_clients::wire_format::SymbolState;
 
-use crate::data_analysis::finnhub_data_row::FinnhubDataRow;
+use crate::data_analysis::trade::Trade;
+use crate::data_analysis::historical_bar::HistoricalBar;
 use crate::data_analysis::candle_stick_service::CandleStickService;
+use crate::data_analysis::trade_consolidator::TradeConsolidator;
+use crate::data_analysis::feed_supervisor::FeedSupervisor;
+use crate::data_analysis::clock::{Clock, SystemClock};
+use crate::data_analysis::market_hours::MarketHours;
 
+use crate::values_store::app_config::{ProviderKind, BarPolicy};
+use crate::values_store::price_precision::PricePrecision;
+use crate::values_store::candle_intervals::CandleIntervals;
+
+/*
+    Cloning is cheap and every clone feeds the same candles, so several providers
+    can run on their own threads against one analysis pipeline.
+*/
+#[derive(Clone)]
 pub struct StockAnalyserWeb {
     trade_map: Arc<RwLock<HashMap<String, CandleStickService>>>,
+    trade_consolidator: Arc<Mutex<TradeConsolidator>>,
+    feed_supervisor: Option<Arc<Mutex<FeedSupervisor>>>,
+    price_precision: Arc<PricePrecision>,
+    candle_intervals: Arc<CandleIntervals>,
+    bar_policy: BarPolicy,
+    market_hours: MarketHours,
+    trade_journal: Option<Sender<Trade>>,
     trade_web_server: TradeWebServer,
+    data_web_client: DataWebClient,
+    clock: Arc<dyn Clock>,
+    last_tick: Arc<Mutex<Option<i64>>>,
 }
 
 impl StockAnalyserWeb {
-    pub fn new(data_web_client: DataWebClient, trade_web_server: TradeWebServer) -> Self {
-        let trade_map_arc = Arc::new(RwLock::new(HashMap::new()));
-        let trade_map_arc_clone = Arc::clone(&trade_map_arc);
+    pub fn new(data_web_client: DataWebClient, trade_web_server: TradeWebServer, trade_consolidator: TradeConsolidator, price_precision: PricePrecision, candle_intervals: CandleIntervals, bar_policy: BarPolicy) -> Self {
+        StockAnalyserWeb{ 
+            trade_map: Arc::new(RwLock::new(HashMap::new())),
+            trade_consolidator: Arc::new(Mutex::new(trade_consolidator)),
+            feed_supervisor: None,
+            price_precision: Arc::new(price_precision),
+            candle_intervals: Arc::new(candle_intervals),
+            bar_policy,
+            market_hours: MarketHours::us_equities(),
+            trade_journal: None,
+            trade_web_server,
+            data_web_client,
+            clock: Arc::new(SystemClock),
+            last_tick: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /*
+        Live mode, ticks on whole seconds of the clock
+    */
+    pub fn start_candle_thread(&self) {
+        let mut stock_analysis_web = self.clone();
 
-        thread::spawn(|| {
-            start_thread(trade_map_arc_clone, data_web_client);
+        thread::spawn(move || {
+            loop {
+                stock_analysis_web.tick();
+            }
         });
+    }
 
-        StockAnalyserWeb{ 
-            trade_map: trade_map_arc,
-            trade_web_server: trade_web_server,
-        }
+    /*
+        Has to be set before the analyser is cloned, the system clock is used otherwise
+    */
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
     }
 
-    pub fn add_finnhub_data(&mut self, json_data: &String) -> bool {
-        let finnhub_data:Vec<FinnhubDataRow> = parse_finnhub_data(json_data);
+    /*
+        Sleeps until the next whole second of the clock and sends the bars due by then
+    */
+    pub fn tick(&mut self) {
+        let now_ms = self.clock.now_millis();
+
+        self.clock.sleep_until(now_ms - now_ms.rem_euclid(1000) + 1000);
+        self.send_due_candles();
+    }
 
-        match finnhub_data.len() {
-            0 => false,
-            _ => { self.add_data(finnhub_data); true },
+    /*
+        Sends the bars at most once per whole second of the clock. The bars themselves are
+        bucketed by the exchange timestamps of the trades and are due once the clock passed
+        their end plus the grace period.
+    */
+    pub fn send_due_candles(&mut self) {
+        let now_ms = self.clock.now_millis();
+        let tick = now_ms - now_ms.rem_euclid(1000);
+
+        // the tick is claimed under the lock, the bars are handed on without it
+        {
+            let mut last_tick = self.last_tick.lock().unwrap();
+
+            if last_tick.is_some_and(|v| tick <= v) {
+                return;
+            }
+
+            *last_tick = Some(tick);
         }
+
+        let list_of_trades = self.due_candles();
+
+        self.trade_web_server.add_candles(&list_of_trades);
+        self.data_web_client.add_candles(list_of_trades);
     }
 
-    pub fn add_eodhd_data(&mut self, json_data: &String) {
-        self.add_single_data(parse_eodhd_data(json_data));
+    /*
+        Has to be set before the analyser is cloned for the provider threads
+    */
+    pub fn set_feed_supervisor(&mut self, feed_supervisor: Arc<Mutex<FeedSupervisor>>) {
+        self.feed_supervisor = Some(feed_supervisor);
     }
 
-    pub fn add_alpaca_data(&mut self, json_data: &String) {
-        self.add_data(parse_alpaca_data(json_data));
+    /*
+        Every valid trade of every provider is journaled, before deduplication and failover
+    */
+    pub fn set_trade_journal(&mut self, trade_journal: Sender<Trade>) {
+        self.trade_journal = Some(trade_journal);
     }
 
-    pub fn add_twelve_data(&mut self, json_data: &String, last_data: &mut HashMap<String, i64>) {
-        let mut twelve_data = parse_twelve_data(json_data);
+    pub fn add_finnhub_data(&mut self, json_data: &str) -> bool {
+        self.add_parsed_data(parse_finnhub_data(json_data), ProviderKind::Finnhub)
+    }
 
-        if twelve_data.s.len() != 0 {
-            let key = twelve_data.s.clone() + "." + &twelve_data.e;
+    pub fn add_eodhd_data(&mut self, json_data: &str) -> bool {
+        self.add_parsed_data(parse_eodhd_data(json_data), ProviderKind::Eodhd)
+    }
+
+    pub fn add_alpaca_data(&mut self, json_data: &str) -> bool {
+        self.add_parsed_data(parse_alpaca_data(json_data), ProviderKind::Alpaca)
+    }
+
+    /*
+        Heartbeats and info messages carry no trades, logging them is left to the client
+    */
+    pub fn add_tiingo_update(&mut self, update: Result<TiingoUpdate, ParseError>) -> bool {
+        let parsed_data = update.map(|v| match v {
+            TiingoUpdate::Trades(trades) => trades,
+            TiingoUpdate::Heartbeat | TiingoUpdate::Info(_) => Vec::new(),
+        });
+
+        self.add_parsed_data(parsed_data, ProviderKind::Tiingo)
+    }
+
+    /*
+        Twelve Data only reports the accumulated day volume, the traded volume is the
+        difference to the previous update of the same symbol and exchange.
+    */
+    pub fn add_twelve_data(&mut self, json_data: &str, last_data: &mut HashMap<String, i64>) -> bool {
+        let mut twelve_data = match parse_twelve_data(json_data) {
+            Ok(v) => v,
+            Err(e) => {
+                println!("Error parsing {} frame: {}", ProviderKind::Twelve, e);
+                return false;
+            },
+        };
+
+        for trade in twelve_data.iter_mut() {
+            let key = format!("{}.{}", trade.symbol, trade.exchange.as_deref().unwrap_or(""));
 
             let prev_volume = match last_data.get(&key) {
                 Some(v) => *v,
-                None => twelve_data.v,
+                None => trade.size,
             };
 
-            last_data.insert(key, twelve_data.v);
+            last_data.insert(key, trade.size);
 
-            twelve_data.v -= prev_volume;
+            trade.size -= prev_volume;
 
-            if twelve_data.v <= 0 {
-                twelve_data.v = 1;
+            if trade.size <= 0 {
+                trade.size = 1;
             }
+        }
+
+        self.add_parsed_data(Ok(twelve_data), ProviderKind::Twelve)
+    }
 
-            self.add_single_data(twelve_data);
+    fn add_parsed_data(&mut self, parsed_data: Result<Vec<Trade>, ParseError>, source: ProviderKind) -> bool {
+        match parsed_data {
+            Ok(v) if v.is_empty() => false,
+            Ok(v) => { self.add_trades(v); true },
+            Err(e) => {
+                println!("Error parsing {} frame: {}", source, e);
+                false
+            },
         }
     }
 
-    fn add_single_data(&mut self, data_row: FinnhubDataRow) {
-        if !data_row.is_valid() {
+    fn add_single_data(&mut self, mut trade: Trade) {
+        if !trade.is_valid() {
             return;
         }
 
-        if !self.trade_map.read().unwrap().contains_key(&data_row.s) {
-            self.trade_map.write().unwrap().insert(
-                data_row.s.clone(),
-                CandleStickService::new(data_row.s.clone()),
-            );
+        if let Some(trade_journal) = &self.trade_journal {
+            let _ = trade_journal.send(trade.clone());
         }
 
+        let price_decimals = self.price_precision.for_symbol(&trade.symbol);
+        trade.price = trade.price.round_to(price_decimals);
+
+        if let Some(feed_supervisor) = &self.feed_supervisor {
+            let mut feed_supervisor = feed_supervisor.lock().unwrap();
+
+            feed_supervisor.record_trade(trade.source, &trade.symbol, self.clock.now_millis());
+
+            if !feed_supervisor.is_active(trade.source, &trade.symbol) {
+                return;
+            }
+        }
+
+        if !self.trade_consolidator.lock().unwrap().accept(&trade) {
+            return;
+        }
+
+        let mut tmp_trade_map = self.trade_map.write().unwrap();
+        let candle_stick_service:&mut CandleStickService = tmp_trade_map
+            .entry(trade.symbol.clone())
+            .or_insert_with(|| self.new_candle_stick_service(&trade.symbol));
+
+        candle_stick_service.add_trade(&trade);
+        candle_stick_service.set_last_update(self.clock.now_millis());
+        drop(tmp_trade_map);
+
+        self.trade_web_server.add_trade(trade, price_decimals);
+    }
+
+    /*
+        Seeds the candles with bars of a vendor's history, they are sent to the data store
+        like live bars. Has to happen before the live trades of the symbol arrive.
+    */
+    pub fn add_history(&mut self, bars: Vec<HistoricalBar>) {
         let mut tmp_trade_map = self.trade_map.write().unwrap();
-        let candle_stick_service:&mut CandleStickService = tmp_trade_map.get_mut(&data_row.s).unwrap();
 
-        candle_stick_service.add_trade(&data_row);
+        for mut bar in bars.into_iter().filter(|v| v.is_valid()) {
+            let price_decimals = self.price_precision.for_symbol(&bar.symbol);
+
+            bar.open_price = bar.open_price.round_to(price_decimals);
+            bar.high_price = bar.high_price.round_to(price_decimals);
+            bar.low_price = bar.low_price.round_to(price_decimals);
+            bar.close_price = bar.close_price.round_to(price_decimals);
 
-        self.trade_web_server.add_trade(data_row);
+            tmp_trade_map
+                .entry(bar.symbol.clone())
+                .or_insert_with(|| self.new_candle_stick_service(&bar.symbol))
+                .add_bar(&bar);
+        }
     }
 
-    fn add_data(&mut self, data_rows: Vec<FinnhubDataRow>) {
-        for data_row in data_rows {
-            self.add_single_data(data_row);
+    fn new_candle_stick_service(&self, symbol: &str) -> CandleStickService {
+        CandleStickService::new(
+            symbol.to_string(),
+            self.price_precision.for_symbol(symbol),
+            self.candle_intervals.for_symbol(symbol),
+            self.bar_policy,
+            self.market_hours,
+        )
+    }
+
+    pub fn add_trades(&mut self, trades: Vec<Trade>) {
+        for trade in trades {
+            self.add_single_data(trade);
         }
     }
-}
 
-fn start_thread(trade_map: Arc<RwLock<HashMap<String, CandleStickService>>>, mut data_web_client: DataWebClient) {
-    let mut target_time = SystemTime::now();
-    
-    loop {
-        target_time.add_assign(Duration::from_millis(1000));
+    /*
+        State of one symbol or of every symbol, sorted by symbol
+    */
+    pub fn symbol_states(&self, symbol: Option<&str>) -> Vec<SymbolState> {
+        let now_ms = self.clock.now_millis();
+        let tmp_trade_map = self.trade_map.read().unwrap();
 
-        match target_time.duration_since(SystemTime::now()) {
-            Ok(v) => thread::sleep(v),
-            Err(_) => (),
-        };
+        let mut list_of_states: Vec<SymbolState> = tmp_trade_map.iter()
+            .filter(|(key, _)| symbol.is_none_or(|v| v == key.as_str()))
+            .map(|(_, value)| value.state(now_ms))
+            .collect();
 
+        list_of_states.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        list_of_states
+    }
+
+    fn due_candles(&mut self) -> Vec<DataTradeModel> {
         let mut list_of_trades:Vec<DataTradeModel> = Vec::new();
-        let base_time = (target_time.duration_since(UNIX_EPOCH).expect("Time Went backwards").as_millis() as i64) - 1000;
 
-        for (_key, value) in trade_map.write().unwrap().iter_mut() {
-            for mut trade in value.get_trades().into_iter() {
-                trade.timestamp = trade.timestamp.max(base_time);
+        for (_key, value) in self.trade_map.write().unwrap().iter_mut() {
+            list_of_trades.append(&mut value.get_trades(self.clock.as_ref()));
+        }
 
-                list_of_trades.push(trade);
-            }
+        list_of_trades
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::values_store::app_config::{BarPolicy, DedupRule, LateTradePolicy, OverflowPolicy, ProviderKind};
+    use crate::values_store::price_precision::PricePrecision;
+    use crate::values_store::candle_intervals::CandleIntervals;
+    use crate::database_clients::data_web_client::{DataWebClient, DataTradeModel};
+    use crate::database_clients::trade_web_server::TradeWebServer;
+    use crate::data_analysis::stock_analysis::StockAnalyserWeb;
+    use crate::data_analysis::trade_consolidator::TradeConsolidator;
+    use crate::data_analysis::clock::{Clock, ManualClock};
+    use crate::data_analysis::price::Price;
+    use crate::data_analysis::trade::Trade;
+
+    // 2024-09-06 15:27:00 UTC
+    const MINUTE: i64 = 1_725_636_420_000;
+
+    fn analyser(clock: &ManualClock) -> StockAnalyserWeb {
+        let mut stock_analysis_web = StockAnalyserWeb::new(
+            DataWebClient::new("ws://127.0.0.1:9", 1_000, OverflowPolicy::DropOldest),
+            TradeWebServer::new("127.0.0.1:9"),
+            TradeConsolidator::new(DedupRule::FirstArrival, ProviderKind::Finnhub),
+            PricePrecision::new(2),
+            CandleIntervals::new(vec![60]),
+            BarPolicy { grace_ms: 500, late_trades: LateTradePolicy::Drop },
+        );
+
+        stock_analysis_web.set_clock(Arc::new(clock.clone()));
+
+        stock_analysis_web
+    }
+
+
+    fn bar(timestamp: i64, stock_interval: usize, prices: [f64; 5], volume_moved: i64, num_of_trades: i64) -> DataTradeModel {
+        DataTradeModel {
+            timestamp,
+            stock_name: "AAPL".to_string(),
+            stock_interval,
+            price_decimals: 2,
+            open_price: Price::from_f64(prices[0]).unwrap(),
+            high_price: Price::from_f64(prices[1]).unwrap(),
+            low_price: Price::from_f64(prices[2]).unwrap(),
+            close_price: Price::from_f64(prices[3]).unwrap(),
+            vwap: Price::from_f64(prices[4]).unwrap(),
+            volume_moved,
+            num_of_trades,
         }
+    }
+
+    #[test]
+    fn bars_come_out_as_the_clock_steps() {
+        let clock = ManualClock::new(MINUTE);
+        let mut stock_analysis_web = analyser(&clock);
+
+        stock_analysis_web.add_trades(vec![
+            Trade::fixture("AAPL", 10.0, MINUTE + 100),
+            Trade::fixture("AAPL", 12.0, MINUTE + 900).with_size(300),
+            Trade::fixture("AAPL", 11.0, MINUTE + 1_200),
+        ]);
+
+        clock.set(MINUTE + 1_499);
+        assert_eq!(stock_analysis_web.due_candles(), Vec::new());
+
+        clock.set(MINUTE + 1_500);
+        assert_eq!(stock_analysis_web.due_candles(), vec![
+            bar(MINUTE, 1, [10.0, 12.0, 10.0, 12.0, 11.5], 400, 2),
+        ]);
+
+        clock.set(MINUTE + 2_500);
+        assert_eq!(stock_analysis_web.due_candles(), vec![
+            bar(MINUTE + 1_000, 1, [11.0, 11.0, 11.0, 11.0, 11.0], 100, 1),
+        ]);
+
+        clock.set(MINUTE + 60_500);
+        let list_of_trades = stock_analysis_web.due_candles();
+
+        assert_eq!(list_of_trades.len(), 59);
+        assert_eq!(list_of_trades[0], bar(MINUTE + 2_000, 1, [11.0, 11.0, 11.0, 11.0, 11.0], 0, 0));
+        assert_eq!(list_of_trades[58], bar(MINUTE, 60, [10.0, 12.0, 10.0, 11.0, 11.4], 500, 3));
+    }
+
+    #[test]
+    fn tick_waits_for_the_next_whole_second() {
+        let clock = ManualClock::new(MINUTE + 1_600);
+        let mut stock_analysis_web = analyser(&clock);
+
+        stock_analysis_web.add_trades(vec![Trade::fixture("AAPL", 10.0, MINUTE + 100)]);
+        stock_analysis_web.tick();
+
+        assert_eq!(clock.now_millis(), MINUTE + 2_000);
+        assert_eq!(*stock_analysis_web.last_tick.lock().unwrap(), Some(MINUTE + 2_000));
+        assert_eq!(stock_analysis_web.due_candles(), Vec::new());
+
+        stock_analysis_web.add_trades(vec![Trade::fixture("AAPL", 11.0, MINUTE + 2_100)]);
+
+        clock.set(MINUTE + 2_900);
+        stock_analysis_web.send_due_candles();
+
+        assert_eq!(*stock_analysis_web.last_tick.lock().unwrap(), Some(MINUTE + 2_000));
+        assert_eq!(stock_analysis_web.due_candles(), vec![
+            bar(MINUTE + 1_000, 1, [10.0, 10.0, 10.0, 10.0, 10.0], 0, 0),
+        ]);
+
+        stock_analysis_web.tick();
 
-        data_web_client.add_finnhub_data(list_of_trades);
+        assert_eq!(clock.now_millis(), MINUTE + 3_000);
+        assert_eq!(*stock_analysis_web.last_tick.lock().unwrap(), Some(MINUTE + 3_000));
     }
 }
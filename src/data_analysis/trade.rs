@@ -0,0 +1,83 @@
+use std::fmt;
+
+use chrono::{DateTime, Utc, TimeZone};
+
+use crate::values_store::app_config::ProviderKind;
+use crate::data_analysis::price::Price;
+
+/*
+    A single print on the tape, independent of the vendor that reported it.
+    Every parser converts its own message format into this type.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trade {
+    pub symbol: String,
+    pub exchange: Option<String>,
+    pub price: Price,
+    pub size: i64,
+    pub timestamp: DateTime<Utc>,
+    pub conditions: Vec<String>,
+    pub source: ProviderKind,
+    pub trade_id: Option<String>,
+}
+
+impl Trade {
+    pub fn timestamp_from_millis(timestamp_ms: i64) -> Option<DateTime<Utc>> {
+        Utc.timestamp_millis_opt(timestamp_ms).single()
+    }
+
+    /*
+        Vendors send sizes as JSON numbers, fractions of a unit are rounded to the nearest
+        whole one rather than cut off
+    */
+    pub fn size_from_f64(size: f64) -> Option<i64> {
+        let size = size.round();
+
+        match size.is_finite() && size.abs() < i64::MAX as f64 {
+            true => Some(size as i64),
+            false => None,
+        }
+    }
+
+    /*
+        Unix milliseconds, the resolution used for candles
+    */
+    pub fn timestamp_millis(&self) -> i64 {
+        self.timestamp.timestamp_millis()
+    }
+
+    pub fn is_valid(&self) -> bool {
+        !self.symbol.is_empty()
+        && self.price > Price::ZERO
+        && self.size >= 0
+        && self.timestamp_millis() != 0
+    }
+}
+
+impl fmt::Display for Trade {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{};{};{};{}", self.symbol, self.price, self.size, self.timestamp_millis())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data_analysis::price::Price;
+    use crate::data_analysis::trade::Trade;
+
+    #[test]
+    fn trades_need_a_price_and_a_timestamp() {
+        assert!(Trade::fixture("AAPL", 10.0, 1_725_636_476_438).is_valid());
+        assert!(!Trade { price: Price::ZERO, ..Trade::fixture("AAPL", 10.0, 1_725_636_476_438) }.is_valid());
+        assert!(!Trade::fixture("AAPL", 10.0, 0).is_valid());
+        assert!(!Trade::fixture("", 10.0, 1_725_636_476_438).is_valid());
+    }
+
+    #[test]
+    fn sizes_are_rounded() {
+        assert_eq!(Trade::size_from_f64(2.6), Some(3));
+        assert_eq!(Trade::size_from_f64(0.00104), Some(0));
+        assert_eq!(Trade::size_from_f64(f64::NAN), None);
+        assert_eq!(Trade::size_from_f64(1e30), None);
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::values_store::app_config::{DedupRule, ProviderKind};
+use crate::data_analysis::trade::Trade;
+use crate::data_analysis::price::Price;
+
+struct SeenTrade {
+    price: Price,
+    volume: i64,
+    timestamp: i64,
+    source: ProviderKind,
+}
+
+#[derive(Default)]
+struct SymbolTape {
+    seen_trades: VecDeque<SeenTrade>,
+    last_primary: i64,
+}
+
+/*
+    Merges the trades of several providers into one tape. Two trades from different
+    providers are considered the same print if symbol, price and volume match and their
+    exchange timestamps are at most match_window_ms apart.
+*/
+pub struct TradeConsolidator {
+    rule: DedupRule,
+    primary: ProviderKind,
+    match_window_ms: i64,
+    primary_gap_ms: i64,
+    tapes: HashMap<String, SymbolTape>,
+}
+
+impl TradeConsolidator {
+    pub fn new(rule: DedupRule, primary: ProviderKind) -> Self {
+        TradeConsolidator {
+            rule,
+            primary,
+            match_window_ms: 1_000,
+            primary_gap_ms: 5_000,
+            tapes: HashMap::new(),
+        }
+    }
+
+    /*
+        Returns true if the trade should be part of the consolidated tape
+    */
+    pub fn accept(&mut self, trade: &Trade) -> bool {
+        let timestamp = trade.timestamp_millis();
+        let tape = self.tapes.entry(trade.symbol.clone()).or_default();
+
+        while let Some(front) = tape.seen_trades.front() {
+            if front.timestamp >= timestamp - self.match_window_ms {
+                break;
+            }
+
+            tape.seen_trades.pop_front();
+        }
+
+        let is_primary = trade.source == self.primary;
+
+        if is_primary {
+            tape.last_primary = tape.last_primary.max(timestamp);
+        }
+
+        let duplicate = tape.seen_trades.iter().position(|seen| {
+            seen.source != trade.source
+            && seen.price == trade.price
+            && seen.volume == trade.size
+            && (seen.timestamp - timestamp).abs() <= self.match_window_ms
+        });
+
+        // a print cancels at most one copy, identical prints of one feed are real trades
+        if let Some(index) = duplicate {
+            tape.seen_trades.remove(index);
+            return false;
+        }
+
+        if let DedupRule::PrimarySecondary = self.rule {
+            if !is_primary && timestamp - tape.last_primary <= self.primary_gap_ms {
+                return false;
+            }
+        }
+
+        tape.seen_trades.push_back(SeenTrade {
+            price: trade.price,
+            volume: trade.size,
+            timestamp,
+            source: trade.source,
+        });
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::values_store::app_config::{DedupRule, ProviderKind};
+    use crate::data_analysis::trade::Trade;
+    use crate::data_analysis::trade_consolidator::TradeConsolidator;
+
+
+    #[test]
+    fn first_arrival_drops_copies_from_other_providers() {
+        let mut consolidator = TradeConsolidator::new(DedupRule::FirstArrival, ProviderKind::Finnhub);
+
+        assert!(consolidator.accept(&Trade::fixture("AAPL", 156.97, 1_000).with_source(ProviderKind::Alpaca)));
+        assert!(!consolidator.accept(&Trade::fixture("AAPL", 156.97, 1_020)));
+        assert!(consolidator.accept(&Trade::fixture("AAPL", 156.98, 1_030)));
+        assert!(consolidator.accept(&Trade::fixture("AAPL", 156.97, 1_040).with_source(ProviderKind::Alpaca)));
+        assert!(consolidator.accept(&Trade::fixture("AAPL", 156.97, 5_000)));
+    }
+
+    #[test]
+    fn identical_prints_are_matched_once() {
+        let mut consolidator = TradeConsolidator::new(DedupRule::FirstArrival, ProviderKind::Finnhub);
+
+        assert!(consolidator.accept(&Trade::fixture("AAPL", 10.00, 1_000).with_source(ProviderKind::Alpaca)));
+        assert!(!consolidator.accept(&Trade::fixture("AAPL", 10.00, 1_010)));
+        assert!(consolidator.accept(&Trade::fixture("AAPL", 10.00, 1_020)));
+
+        assert!(consolidator.accept(&Trade::fixture("AAPL", 20.00, 3_000)));
+        assert!(consolidator.accept(&Trade::fixture("AAPL", 20.00, 3_010)));
+        assert!(!consolidator.accept(&Trade::fixture("AAPL", 20.00, 3_020).with_source(ProviderKind::Alpaca)));
+        assert!(!consolidator.accept(&Trade::fixture("AAPL", 20.00, 3_030).with_source(ProviderKind::Alpaca)));
+        assert!(consolidator.accept(&Trade::fixture("AAPL", 20.00, 3_040).with_source(ProviderKind::Alpaca)));
+    }
+
+    #[test]
+    fn primary_secondary_only_fills_gaps() {
+        let mut consolidator = TradeConsolidator::new(DedupRule::PrimarySecondary, ProviderKind::Finnhub);
+
+        assert!(consolidator.accept(&Trade::fixture("AAPL", 1.00, 1_000).with_size(1)));
+        assert!(!consolidator.accept(&Trade::fixture("AAPL", 1.01, 2_000).with_size(1).with_source(ProviderKind::Alpaca)));
+        assert!(consolidator.accept(&Trade::fixture("AAPL", 1.02, 10_000).with_size(1).with_source(ProviderKind::Alpaca)));
+        assert!(!consolidator.accept(&Trade::fixture("AAPL", 1.02, 10_010).with_size(1)));
+        assert!(consolidator.accept(&Trade::fixture("AAPL", 1.03, 10_020).with_size(1)));
+        assert!(!consolidator.accept(&Trade::fixture("AAPL", 1.04, 11_000).with_size(1).with_source(ProviderKind::Alpaca)));
+    }
+}
@@ -0,0 +1,95 @@
+use chrono::DateTime;
+use serde::Deserialize;
+
+use crate::data_analysis::historical_bar::HistoricalBar;
+use crate::data_analysis::price::Price;
+use crate::data_parsers::parse_error::ParseError;
+
+#[derive(Deserialize)]
+struct AlpacaBars {
+    #[serde(default)]
+    bars: Option<Vec<AlpacaBar>>,
+    #[serde(default)]
+    next_page_token: Option<String>,
+    #[serde(default)]
+    message: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AlpacaBar {
+    t: String,
+    o: Price,
+    h: Price,
+    l: Price,
+    c: Price,
+    v: f64,
+    #[serde(default)]
+    n: i64,
+    #[serde(default)]
+    vw: Option<Price>,
+}
+
+/*
+    /v2/stocks/{symbol}/bars answers with {"bars":[{"t":"2024-09-06T15:26:00Z","o":..,"h":..,
+    "l":..,"c":..,"v":..,"n":..,"vw":..}],"symbol":"AAPL","next_page_token":null}.
+    Symbols without bars in the range have "bars": null, errors only carry a message.
+    Returns the bars and the token of the next page if there is one.
+*/
+pub fn parse_alpaca_bars(symbol: &str, interval_seconds: usize, json_data: &str) -> Result<(Vec<HistoricalBar>, Option<String>), ParseError> {
+    let response: AlpacaBars = serde_json::from_str(json_data)?;
+
+    if let Some(message) = response.message {
+        return Err(ParseError::Vendor(message));
+    }
+
+    let mut list_of_bars: Vec<HistoricalBar> = Vec::new();
+
+    for bar in response.bars.unwrap_or_default().into_iter() {
+        let timestamp = match DateTime::parse_from_rfc3339(&bar.t) {
+            Ok(v) => v.timestamp_millis(),
+            Err(_) => return Err(ParseError::InvalidField("t".to_string(), bar.t)),
+        };
+
+        list_of_bars.push(HistoricalBar {
+            symbol: symbol.to_string(),
+            timestamp,
+            interval_seconds,
+            open_price: bar.o,
+            high_price: bar.h,
+            low_price: bar.l,
+            close_price: bar.c,
+            vwap: bar.vw,
+            volume: bar.v as i64,
+            num_of_trades: bar.n,
+        });
+    }
+
+    Ok((list_of_bars, response.next_page_token))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data_parsers::alpaca_history_parser::parse_alpaca_bars;
+    use crate::data_parsers::fixtures::fixture;
+
+    #[test]
+    fn parse_bars() {
+        let (bars, next_page_token) = parse_alpaca_bars("AAPL", 60, &fixture("alpaca/history/bars.json")).unwrap();
+
+        assert_eq!(bars.len(), 2);
+        assert_eq!(bars[0].timestamp, 1_725_636_360_000);
+        assert_eq!(bars[0].close_price.to_string(), "222.3");
+        assert_eq!(bars[0].vwap.unwrap().to_string(), "222.281");
+        assert_eq!(bars[1].num_of_trades, 9);
+        assert!(bars.iter().all(|v| v.is_valid()));
+        assert_eq!(next_page_token, None);
+
+        let (bars, next_page_token) = parse_alpaca_bars("AAPL", 60, &fixture("alpaca/history/first_page.json")).unwrap();
+
+        assert_eq!(bars.len(), 1);
+        assert_eq!(next_page_token, Some("QUFQTHxNfDE3MjU2MzYzMDAwMDA=".to_string()));
+
+        assert!(parse_alpaca_bars("AAPL", 60, "{\"bars\":null,\"symbol\":\"AAPL\"}").unwrap().0.is_empty());
+        assert!(parse_alpaca_bars("AAPL", 60, &fixture("alpaca/history/forbidden.json")).is_err());
+    }
+}
@@ -1,89 +1,143 @@
-use crate::data_analysis::finnhub_data_row::FinnhubDataRow;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::data_analysis::trade::Trade;
+use crate::data_analysis::price::Price;
+use crate::values_store::app_config::ProviderKind;
+use crate::data_parsers::parse_error::ParseError;
+
+#[derive(Deserialize)]
+struct AlpacaTrade {
+    #[serde(rename = "S")]
+    symbol: String,
+    #[serde(rename = "x")]
+    exchange: String,
+    #[serde(rename = "p")]
+    price: Price,
+    #[serde(rename = "s")]
+    size: f64,
+    #[serde(rename = "t")]
+    timestamp: String,
+    #[serde(rename = "c", default)]
+    conditions: Vec<String>,
+    #[serde(rename = "i")]
+    trade_id: i64,
+}
 
-/*
-    Find the first occurence of data in string and parse the data from the given index
-*/
-/*
-    Find the first occurence of data in string and parse the data from the given index
-*/
-pub fn parse_alpaca_data(json_data: &String) -> Vec<FinnhubDataRow> {
-    let mut open_brackets: i32 = 0;
-
-    let mut tmp:String = String::new();
-    let mut key:String = String::new();
-    let mut open_string: bool = false;
-
-    let mut current_datapoint:FinnhubDataRow = FinnhubDataRow::new();
-    let mut list_of_datapoints:Vec<FinnhubDataRow> = Vec::new();
-    
-    for c in json_data.chars() {
-        if c == '\n' || c == '\r' || c == ' ' { continue; }
-
-        if c == '"' {
-            match open_string {
-                true => open_brackets -= 1,
-                false => open_brackets += 1,
-            };
-            
-            open_string = !open_string;
-            
-            continue;
-        }
-        
-        if c == '{' || c == '[' {
-            open_brackets += 1;
-            continue;
-        }
+#[derive(Deserialize)]
+struct AlpacaError {
+    code: i64,
+    msg: String,
+}
 
-        if c == '}' || c == ']' {
-            open_brackets -= 1;
-            continue;
-        }
+impl TryFrom<AlpacaTrade> for Trade {
+    type Error = ParseError;
+
+    fn try_from(trade: AlpacaTrade) -> Result<Self, Self::Error> {
+        let timestamp = match DateTime::parse_from_rfc3339(&trade.timestamp) {
+            Ok(v) => v.with_timezone(&Utc),
+            Err(_) => return Err(ParseError::InvalidField("t".to_string(), trade.timestamp)),
+        };
+
+        let size = match Trade::size_from_f64(trade.size) {
+            Some(v) => v,
+            None => return Err(ParseError::InvalidField("s".to_string(), trade.size.to_string())),
+        };
+
+        Ok(Trade {
+            symbol: trade.symbol,
+            exchange: Some(trade.exchange),
+            price: trade.price,
+            size,
+            timestamp,
+            conditions: trade.conditions,
+            source: ProviderKind::Alpaca,
+            trade_id: Some(trade.trade_id.to_string()),
+        })
+    }
+}
 
-        if c == ':' && open_brackets == 2 {
-            key = tmp;
-            tmp = String::new();
-            
-            continue;
-        }
+/*
+    Alpaca sends arrays of messages, distinguished by "T". Only trades ("t") are parsed,
+    trades reported through the FINRA ADF (exchange "D") are skipped.
+*/
+pub fn parse_alpaca_data(json_data: &str) -> Result<Vec<Trade>, ParseError> {
+    let messages: Vec<Value> = serde_json::from_str(json_data)?;
 
-        if c == ',' && open_brackets <= 2 {
-            current_datapoint.set_alpaca_data(&key, &tmp);
-            tmp.clear();
+    let mut list_of_trades: Vec<Trade> = Vec::new();
 
-            match open_brackets {
-                1 => {
-                    list_of_datapoints.push(current_datapoint);
-                    current_datapoint = FinnhubDataRow::new();
-                },
-                _ => (),
-            }
+    for message in messages.into_iter() {
+        match message.get("T").and_then(|v| v.as_str()) {
+            Some("t") => (),
+            Some("error") => {
+                let error: AlpacaError = serde_json::from_value(message)?;
+                return Err(ParseError::Vendor(format!("{} {}", error.code, error.msg)));
+            },
+            Some(_) => continue,
+            None => return Err(ParseError::InvalidField("T".to_string(), message.to_string())),
+        };
 
+        let trade: AlpacaTrade = serde_json::from_value(message)?;
 
+        if trade.exchange == "D" {
             continue;
         }
 
-        tmp.push(c);
+        list_of_trades.push(Trade::try_from(trade)?);
     }
 
-    current_datapoint.set_alpaca_data(&key, &tmp);
-    list_of_datapoints.push(current_datapoint);
-
-    list_of_datapoints
+    Ok(list_of_trades)
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::values_store::app_config::ProviderKind;
     use crate::data_parsers::alpaca_parser::parse_alpaca_data;
+    use crate::data_parsers::fixtures::{read_fixtures, fixture};
 
     #[test]
     fn parse_alpaca_data_test() {
         let input = "[{\"T\":\"t\",\"S\":\"TSM\",\"i\":55397666350414,\"x\":\"V\",\"p\":156.97,\"s\":100,\"c\":[\" \"],\"z\":\"A\",\"t\":\"2024-09-06T15:27:56.438925312Z\"}]".to_string();
 
-        let data_row = parse_alpaca_data(&input);
+        let trades = parse_alpaca_data(&input).unwrap();
+
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].symbol, "TSM");
+        assert!(trades[0].is_valid());
+    }
+
+    #[test]
+    fn parse_alpaca_batch() {
+        let trades = parse_alpaca_data(&fixture("alpaca/valid/trades.json")).unwrap();
+
+        assert_eq!(trades.len(), 2);
+        assert_eq!(trades[0].symbol, "AAPL");
+        assert_eq!(trades[0].exchange, Some("V".to_string()));
+        assert_eq!(trades[0].price.to_string(), "222.25");
+        assert_eq!(trades[0].size, 100);
+        assert_eq!(trades[0].timestamp_millis(), 1725636476438);
+        assert_eq!(trades[0].conditions, vec!["@"]);
+        assert_eq!(trades[0].source, ProviderKind::Alpaca);
+        assert_eq!(trades[0].trade_id, Some("52983525029461".to_string()));
+        assert_eq!(trades[1].symbol, "MSFT");
+    }
+
+    #[test]
+    fn skip_alpaca_control_messages() {
+        assert!(parse_alpaca_data(&fixture("alpaca/valid/authenticated.json")).unwrap().is_empty());
+        assert!(parse_alpaca_data(&fixture("alpaca/valid/subscription.json")).unwrap().is_empty());
+        assert!(parse_alpaca_data(&fixture("alpaca/invalid/auth_failed.json")).is_err());
+    }
 
-        println!("{:?}", data_row);
+    #[test]
+    fn alpaca_fixture_corpus() {
+        for (name, frame) in read_fixtures("alpaca/valid") {
+            assert!(parse_alpaca_data(&frame).is_ok(), "{}", name);
+        }
 
-        assert!(false);
-    }   
-}
\ No newline at end of file
+        for (name, frame) in read_fixtures("alpaca/invalid") {
+            assert!(parse_alpaca_data(&frame).is_err(), "{}", name);
+        }
+    }
+}
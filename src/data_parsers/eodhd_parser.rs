@@ -1,59 +1,104 @@
-use crate::data_analysis::finnhub_data_row::FinnhubDataRow;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::data_analysis::trade::Trade;
+use crate::data_analysis::price::Price;
+use crate::values_store::app_config::ProviderKind;
+use crate::data_parsers::parse_error::ParseError;
+
+#[derive(Deserialize)]
+struct EodhdTrade {
+    s: String,
+    p: Price,
+    v: f64,
+    t: i64,
+    #[serde(default)]
+    c: Option<Vec<i64>>,
+}
+
+impl TryFrom<EodhdTrade> for Trade {
+    type Error = ParseError;
+
+    fn try_from(trade: EodhdTrade) -> Result<Self, Self::Error> {
+        let timestamp = match Trade::timestamp_from_millis(trade.t) {
+            Some(v) => v,
+            None => return Err(ParseError::InvalidField("t".to_string(), trade.t.to_string())),
+        };
+
+        let size = match Trade::size_from_f64(trade.v) {
+            Some(v) => v,
+            None => return Err(ParseError::InvalidField("v".to_string(), trade.v.to_string())),
+        };
+
+        Ok(Trade {
+            symbol: trade.s,
+            exchange: None,
+            price: trade.p,
+            size,
+            timestamp,
+            conditions: trade.c.unwrap_or_default().iter().map(|c| c.to_string()).collect(),
+            source: ProviderKind::Eodhd,
+            trade_id: None,
+        })
+    }
+}
 
 /*
-    Find the first occurence of data in string and parse the data from the given index
+    {"s":"AAPL","p":222.25,"c":[12,37],"v":100,"dp":false,"ms":"open","t":1725636476438}
+    The status message sent after connecting yields no trade.
 */
-pub fn parse_eodhd_data(json_data: &String) -> FinnhubDataRow {
-    let json_chars = json_data.chars();
-
-    let mut open_brackets: i32 = 0;
-    let mut open_string: bool = false;
-
-    let mut tmp:String = String::new();
-    let mut key:String = String::new();
-
-    let mut current_datapoint:FinnhubDataRow = FinnhubDataRow::new();
-    
-    for c in json_chars.into_iter() {
-        let mut push_char: bool = false;
-
-        match c {
-            '\n' | '\r' | ' ' => (),
-            '"' => {
-                match open_string {
-                    true => open_brackets -= 1,
-                    false => open_brackets += 1,
-                };
-                
-                open_string = !open_string;
-            },
-            '{' | '[' => open_brackets += 1,
-            '}' | ']' => open_brackets -= 1,
-            ':' => {
-                match open_brackets {
-                    1 => {
-                        key = tmp;
-                        tmp = String::new();
-                    },
-                    _ => push_char = true,
-                }
-            },
-            ',' => {
-                if open_brackets <= 1 {
-                    current_datapoint.set_data(&key, &tmp);
-                    tmp.clear();
-                } else {
-                    push_char = true;
-                }
-            },
-            _ => push_char = true,
+pub fn parse_eodhd_data(json_data: &str) -> Result<Vec<Trade>, ParseError> {
+    let message: Value = serde_json::from_str(json_data)?;
+
+    if message.get("status_code").is_some() {
+        return Ok(Vec::new());
+    }
+
+    let trade: EodhdTrade = serde_json::from_value(message)?;
+
+    Ok(vec![Trade::try_from(trade)?])
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::values_store::app_config::ProviderKind;
+    use crate::data_parsers::eodhd_parser::parse_eodhd_data;
+    use crate::data_parsers::fixtures::{read_fixtures, fixture};
+
+    #[test]
+    fn parse_eodhd_trade() {
+        let trades = parse_eodhd_data(&fixture("eodhd/valid/trade.json")).unwrap();
+
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].symbol, "AAPL");
+        assert_eq!(trades[0].price.to_string(), "222.25");
+        assert_eq!(trades[0].size, 100);
+        assert_eq!(trades[0].timestamp_millis(), 1725636476438);
+        assert_eq!(trades[0].conditions, vec!["12", "37"]);
+        assert_eq!(trades[0].source, ProviderKind::Eodhd);
+        assert!(trades[0].is_valid());
+    }
+
+    #[test]
+    fn parse_eodhd_fractional_volume() {
+        let trades = parse_eodhd_data(&fixture("eodhd/valid/fractional_volume.json")).unwrap();
+
+        assert_eq!((trades[0].symbol.as_str(), trades[0].size), ("BTC-USD", 2));
+    }
+
+    #[test]
+    fn parse_eodhd_status() {
+        assert!(parse_eodhd_data(&fixture("eodhd/valid/authorized.json")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn eodhd_fixture_corpus() {
+        for (name, frame) in read_fixtures("eodhd/valid") {
+            assert!(parse_eodhd_data(&frame).is_ok(), "{}", name);
         }
 
-        if push_char {
-            tmp.push(c);
+        for (name, frame) in read_fixtures("eodhd/invalid") {
+            assert!(parse_eodhd_data(&frame).is_err(), "{}", name);
         }
     }
-
-    current_datapoint.set_data(&key, &tmp);
-    current_datapoint
-}
\ No newline at end of file
+}
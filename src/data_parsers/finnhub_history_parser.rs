@@ -0,0 +1,80 @@
+use serde::Deserialize;
+
+use crate::data_analysis::historical_bar::HistoricalBar;
+use crate::data_analysis::price::Price;
+use crate::data_parsers::parse_error::ParseError;
+
+#[derive(Deserialize)]
+struct FinnhubCandles {
+    s: String,
+    #[serde(default)]
+    o: Vec<Price>,
+    #[serde(default)]
+    h: Vec<Price>,
+    #[serde(default)]
+    l: Vec<Price>,
+    #[serde(default)]
+    c: Vec<Price>,
+    #[serde(default)]
+    v: Vec<f64>,
+    #[serde(default)]
+    t: Vec<i64>,
+}
+
+/*
+    /stock/candle answers with one array per field, t is the bar start in seconds:
+    {"c":[222.3],"h":[222.5],"l":[222.0],"o":[222.25],"s":"ok","t":[1725636360],"v":[1200]}
+    Ranges without trades are reported as {"s":"no_data"}.
+*/
+pub fn parse_finnhub_candles(symbol: &str, interval_seconds: usize, json_data: &str) -> Result<Vec<HistoricalBar>, ParseError> {
+    let candles: FinnhubCandles = serde_json::from_str(json_data)?;
+
+    match candles.s.as_str() {
+        "ok" => (),
+        "no_data" => return Ok(Vec::new()),
+        other => return Err(ParseError::Vendor(other.to_string())),
+    };
+
+    let length = candles.t.len();
+
+    for (name, field_length) in [("o", candles.o.len()), ("h", candles.h.len()), ("l", candles.l.len()), ("c", candles.c.len()), ("v", candles.v.len())] {
+        if field_length != length {
+            return Err(ParseError::InvalidField(name.to_string(), format!("{} values for {} timestamps", field_length, length)));
+        }
+    }
+
+    Ok((0..length).map(|i| HistoricalBar {
+        symbol: symbol.to_string(),
+        timestamp: candles.t[i] * 1000,
+        interval_seconds,
+        open_price: candles.o[i],
+        high_price: candles.h[i],
+        low_price: candles.l[i],
+        close_price: candles.c[i],
+        vwap: None,
+        volume: candles.v[i] as i64,
+        num_of_trades: 0,
+    }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data_parsers::finnhub_history_parser::parse_finnhub_candles;
+    use crate::data_parsers::fixtures::fixture;
+
+    #[test]
+    fn parse_candles() {
+        let bars = parse_finnhub_candles("AAPL", 60, &fixture("finnhub/history/candles.json")).unwrap();
+
+        assert_eq!(bars.len(), 2);
+        assert_eq!(bars[0].symbol, "AAPL");
+        assert_eq!(bars[0].timestamp, 1_725_636_360_000);
+        assert_eq!(bars[0].open_price.to_string(), "222.25");
+        assert_eq!(bars[1].low_price.to_string(), "221.95");
+        assert_eq!(bars[1].volume, 800);
+        assert!(bars.iter().all(|v| v.is_valid()));
+
+        assert!(parse_finnhub_candles("AAPL", 60, &fixture("finnhub/history/no_data.json")).unwrap().is_empty());
+        assert!(parse_finnhub_candles("AAPL", 60, "{\"s\":\"ok\",\"t\":[1725636360],\"o\":[]}").is_err());
+    }
+}
@@ -1,125 +1,118 @@
-use crate::data_analysis::finnhub_data_row::FinnhubDataRow;
+use serde::Deserialize;
+
+use crate::data_analysis::trade::Trade;
+use crate::data_analysis::price::Price;
+use crate::values_store::app_config::ProviderKind;
+use crate::data_parsers::parse_error::ParseError;
 
 /*
-    Find the first occurence of data in string and parse the data from the given index
+    A plain struct rather than a tagged enum, serde buffers tagged enums and loses the
+    exact text of the numbers on the way
 */
-pub fn parse_finnhub_data(json_data: &String) -> Vec<FinnhubDataRow> {
-    let n: usize = json_data.len();
-    let json_bytes: &[u8] = json_data.as_bytes();
-
-    let mut open_brackets: i32 = 0;
-    let mut is_left: bool = true;
-
-    let mut tmp:String = String::new();
-    let mut open_string: bool = false;
-    
-    for i in 0..n {
-        if json_bytes[i] == b'\n' || json_bytes[i] == b'\r' || json_bytes[i] == b' ' { continue; }
-        
-        if json_bytes[i] == b'{' || json_bytes[i] == b'[' {
-            open_brackets += 1;
-            continue;
-        }
-
-        if json_bytes[i] == b'}' || json_bytes[i] == b']' {
-            open_brackets -= 1;
-            continue;
-        }
-
-        if open_brackets == 1 && json_bytes[i] == b',' {
-            is_left = true;
-            continue;
-        }
+#[derive(Deserialize)]
+struct FinnhubMessage {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    data: Vec<FinnhubTrade>,
+    #[serde(default)]
+    msg: String,
+}
 
-        if !is_left { continue; }
-
-        if json_bytes[i] == b'"'{
-            match open_string {
-                false => open_string = true,
-                true => {
-                    if tmp == "data" { return parse_data_field(json_bytes, i+2); }
-                    
-                    open_string = false;
-                    is_left = false;
-                    tmp.clear();
-                }
-            };
-
-            continue;
-        }
+#[derive(Deserialize)]
+struct FinnhubTrade {
+    s: String,
+    p: Price,
+    t: i64,
+    v: f64,
+    #[serde(default)]
+    c: Option<Vec<String>>,
+}
 
-        if open_string {
-            tmp.push(char::from(json_bytes[i]));
-        }
+impl TryFrom<FinnhubTrade> for Trade {
+    type Error = ParseError;
+
+    fn try_from(trade: FinnhubTrade) -> Result<Self, Self::Error> {
+        let timestamp = match Trade::timestamp_from_millis(trade.t) {
+            Some(v) => v,
+            None => return Err(ParseError::InvalidField("t".to_string(), trade.t.to_string())),
+        };
+
+        let size = match Trade::size_from_f64(trade.v) {
+            Some(v) => v,
+            None => return Err(ParseError::InvalidField("v".to_string(), trade.v.to_string())),
+        };
+
+        Ok(Trade {
+            symbol: trade.s,
+            exchange: None,
+            price: trade.p,
+            size,
+            timestamp,
+            conditions: trade.c.unwrap_or_default(),
+            source: ProviderKind::Finnhub,
+            trade_id: None,
+        })
     }
-
-    Vec::new()
 }
 
-fn parse_data_field(json_bytes: &[u8], pos: usize) -> Vec<FinnhubDataRow> {
-    let n: usize = json_bytes.len();
-
-    let mut open_brackets: i32 = 0;
-
-    let mut tmp:String = String::new();
-    let mut key:String = String::new();
-    let mut open_string: bool = false;
-
-    let mut current_datapoint:FinnhubDataRow = FinnhubDataRow::new();
-    let mut list_of_datapoints:Vec<FinnhubDataRow> = Vec::new();
-    
-    for i in pos..n {
-        if json_bytes[i] == b'\n' || json_bytes[i] == b'\r' || json_bytes[i] == b' ' { continue; }
-
-        if json_bytes[i] == b'"' {
-            match open_string {
-                true => open_brackets -= 1,
-                false => open_brackets += 1,
-            };
-            
-            open_string = !open_string;
-            
-            continue;
-        }
-        
-        if json_bytes[i] == b'{' || json_bytes[i] == b'[' {
-            open_brackets += 1;
-            continue;
-        }
+/*
+    {"type":"trade","data":[{"c":["1","12"],"p":156.97,"s":"AAPL","t":1575526691134,"v":100}]}
+    Pings and other message types yield no trades.
+*/
+pub fn parse_finnhub_data(json_data: &str) -> Result<Vec<Trade>, ParseError> {
+    let message = serde_json::from_str::<FinnhubMessage>(json_data)?;
 
-        if json_bytes[i] == b'}' || json_bytes[i] == b']' {
-            open_brackets -= 1;
-            continue;
-        }
+    match message.kind.as_str() {
+        "trade" => message.data.into_iter().map(Trade::try_from).collect(),
+        "error" => Err(ParseError::Vendor(message.msg)),
+        _ => Ok(Vec::new()),
+    }
+}
 
-        if json_bytes[i] == b':' && open_brackets == 2 {
-            key = tmp;
-            tmp = String::new();
-            
-            continue;
-        }
+#[cfg(test)]
+mod tests {
+    use crate::values_store::app_config::ProviderKind;
+    use crate::data_parsers::finnhub_parser::parse_finnhub_data;
+    use crate::data_parsers::fixtures::{read_fixtures, fixture};
+
+    #[test]
+    fn parse_finnhub_trades() {
+        let trades = parse_finnhub_data(&fixture("finnhub/valid/trades.json")).unwrap();
+
+        assert_eq!(trades.len(), 2);
+        assert_eq!(trades[0].symbol, "AAPL");
+        assert_eq!(trades[0].price.to_string(), "222.25");
+        assert_eq!(trades[0].size, 100);
+        assert_eq!(trades[0].timestamp_millis(), 1725636476438);
+        assert_eq!(trades[0].conditions, vec!["1", "12"]);
+        assert_eq!(trades[0].source, ProviderKind::Finnhub);
+        assert_eq!(trades[1].symbol, "BINANCE:BTCUSDT");
+        assert!(trades[1].conditions.is_empty());
+        assert!(trades.iter().all(|trade| trade.is_valid()));
+    }
 
-        if json_bytes[i] == b',' && open_brackets <= 2 {
-            current_datapoint.set_data(&key, &tmp);
-            tmp.clear();
+    #[test]
+    fn parse_finnhub_fractional_volume() {
+        let trades = parse_finnhub_data(&fixture("finnhub/valid/fractional_volume.json")).unwrap();
 
-            match open_brackets {
-                0 => break,
-                1 =>{
-                    list_of_datapoints.push(current_datapoint);
-                    current_datapoint = FinnhubDataRow::new();
-                },
-                _ => (),
-            }
+        assert_eq!(trades.iter().map(|v| v.size).collect::<Vec<_>>(), vec![3, 0]);
+        assert!(trades.iter().all(|trade| trade.is_valid()));
+    }
 
+    #[test]
+    fn parse_finnhub_ping() {
+        assert!(parse_finnhub_data(&fixture("finnhub/valid/ping.json")).unwrap().is_empty());
+    }
 
-            continue;
+    #[test]
+    fn finnhub_fixture_corpus() {
+        for (name, frame) in read_fixtures("finnhub/valid") {
+            assert!(parse_finnhub_data(&frame).is_ok(), "{}", name);
         }
 
-        tmp.push(char::from(json_bytes[i]));
+        for (name, frame) in read_fixtures("finnhub/invalid") {
+            assert!(parse_finnhub_data(&frame).is_err(), "{}", name);
+        }
     }
-
-    list_of_datapoints.push(current_datapoint);
-
-    list_of_datapoints
-}
\ No newline at end of file
+}
@@ -0,0 +1,72 @@
+use std::fs;
+use std::path::PathBuf;
+
+use crate::values_store::app_config::ProviderKind;
+use crate::data_analysis::price::Price;
+use crate::data_analysis::trade::Trade;
+use crate::database_clients::wire_format::WireBody;
+
+/*
+    Recorded vendor frames under fixtures/<vendor>/{valid,invalid}, one frame per file
+*/
+fn fixtures_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures")
+}
+
+pub fn fixture(path: &str) -> String {
+    match fs::read_to_string(fixtures_dir().join(path)) {
+        Ok(v) => v,
+        Err(e) => panic!("Cannot read fixture {}: {}", path, e),
+    }
+}
+
+pub fn read_fixtures(dir: &str) -> Vec<(String, String)> {
+    let mut list_of_fixtures: Vec<(String, String)> = Vec::new();
+
+    for entry in fs::read_dir(fixtures_dir().join(dir)).unwrap() {
+        let path = entry.unwrap().path();
+
+        list_of_fixtures.push((path.display().to_string(), fs::read_to_string(&path).unwrap()));
+    }
+
+    assert!(!list_of_fixtures.is_empty(), "No fixtures in {}", dir);
+
+    list_of_fixtures.sort();
+    list_of_fixtures
+}
+
+/*
+    Trade::fixture("AAPL", 10.5, timestamp_ms).with_size(7).with_source(ProviderKind::Alpaca)
+    is a print of 7 shares from Alpaca, without them 100 shares from Finnhub
+*/
+impl Trade {
+    pub fn fixture(symbol: &str, price: f64, timestamp_ms: i64) -> Self {
+        Trade {
+            symbol: symbol.to_string(),
+            exchange: None,
+            price: Price::from_f64(price).unwrap(),
+            size: 100,
+            timestamp: Trade::timestamp_from_millis(timestamp_ms).unwrap(),
+            conditions: Vec::new(),
+            source: ProviderKind::Finnhub,
+            trade_id: None,
+        }
+    }
+
+    pub fn with_size(mut self, size: i64) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn with_source(mut self, source: ProviderKind) -> Self {
+        self.source = source;
+        self
+    }
+}
+
+/*
+    ack("subscribe", &["AAPL"]) is the answer to "subscribe;AAPL"
+*/
+pub fn ack(request: &str, patterns: &[&str]) -> WireBody {
+    WireBody::Ack { request: request.to_string(), patterns: patterns.iter().map(|v| v.to_string()).collect() }
+}
@@ -1,4 +1,11 @@
 pub mod finnhub_parser;
 pub mod eodhd_parser;
 pub mod alpaca_parser;
-pub mod twelve_parser;
\ No newline at end of file
+pub mod twelve_parser;
+pub mod tiingo_parser;
+pub mod finnhub_history_parser;
+pub mod alpaca_history_parser;
+pub mod parse_error;
+
+#[cfg(test)]
+pub mod fixtures;
\ No newline at end of file
@@ -0,0 +1,31 @@
+use std::fmt;
+use std::error;
+
+/*
+    A frame that couldn't be turned into trades. Control messages like heartbeats
+    or subscription acknowledgements are not errors, they just yield no trades.
+*/
+#[derive(Debug)]
+pub enum ParseError {
+    Json(serde_json::Error),
+    InvalidField(String, String),
+    Vendor(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Json(e) => write!(f, "Malformed json: {}", e),
+            ParseError::InvalidField(field, value) => write!(f, "Invalid value for {}: {}", field, value),
+            ParseError::Vendor(msg) => write!(f, "Error message from provider: {}", msg),
+        }
+    }
+}
+
+impl error::Error for ParseError {}
+
+impl From<serde_json::Error> for ParseError {
+    fn from(e: serde_json::Error) -> Self {
+        ParseError::Json(e)
+    }
+}
@@ -0,0 +1,169 @@
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::data_analysis::trade::Trade;
+use crate::data_analysis::price::Price;
+use crate::values_store::app_config::ProviderKind;
+use crate::data_parsers::parse_error::ParseError;
+
+#[derive(Deserialize)]
+struct TiingoMessage {
+    #[serde(rename = "messageType")]
+    message_type: String,
+    #[serde(default)]
+    data: Value,
+    #[serde(default)]
+    response: Value,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TiingoUpdate {
+    Trades(Vec<Trade>),
+    Heartbeat,
+    Info(String),
+}
+
+/*
+    Tiingo IEX sends {"messageType": "A"|"H"|"I", ..., "data": [...]}. Only "A" messages carry
+    market data, "H" is a heartbeat and "I" an info message like the subscription response.
+    The data array is positional, index 0 is the update type ("T" trade, "Q" quote, "B" break).
+*/
+pub fn parse_tiingo_data(json_data: &str, include_quotes: bool) -> Result<TiingoUpdate, ParseError> {
+    let message: TiingoMessage = serde_json::from_str(json_data)?;
+
+    match message.message_type.as_str() {
+        "A" => (),
+        "H" => return Ok(TiingoUpdate::Heartbeat),
+        "I" => return Ok(TiingoUpdate::Info(message.response.to_string())),
+        "E" => return Err(ParseError::Vendor(message.response.to_string())),
+        other => return Err(ParseError::InvalidField("messageType".to_string(), other.to_string())),
+    };
+
+    let data: Vec<Value> = serde_json::from_value(message.data)?;
+
+    /*
+        2 time in nanoseconds, 3 ticker, 6 mid price, 9 last price, 10 last size
+    */
+    let (price_index, volume) = match field_str(&data, 0)? {
+        "T" => (9, field_f64(&data, 10)? as i64),
+        "Q" if include_quotes => (6, 0),
+        _ => return Ok(TiingoUpdate::Trades(Vec::new())),
+    };
+
+    let timestamp = match Trade::timestamp_from_millis(field_i64(&data, 2)? / 1_000_000) {
+        Some(v) => v,
+        None => return Err(invalid_field(&data, 2)),
+    };
+
+    Ok(TiingoUpdate::Trades(vec![Trade {
+        symbol: field_str(&data, 3)?.to_uppercase(),
+        exchange: Some("IEX".to_string()),
+        price: field_price(&data, price_index)?,
+        size: volume,
+        timestamp,
+        conditions: Vec::new(),
+        source: ProviderKind::Tiingo,
+        trade_id: None,
+    }]))
+}
+
+fn field_str(data: &[Value], index: usize) -> Result<&str, ParseError> {
+    match data.get(index).and_then(|v| v.as_str()) {
+        Some(v) => Ok(v),
+        None => Err(invalid_field(data, index)),
+    }
+}
+
+fn field_f64(data: &[Value], index: usize) -> Result<f64, ParseError> {
+    match data.get(index).and_then(|v| v.as_f64()) {
+        Some(v) => Ok(v),
+        None => Err(invalid_field(data, index)),
+    }
+}
+
+fn field_price(data: &[Value], index: usize) -> Result<Price, ParseError> {
+    match data.get(index).and_then(|v| v.as_number()).and_then(|v| Price::parse(&v.to_string())) {
+        Some(v) => Ok(v),
+        None => Err(invalid_field(data, index)),
+    }
+}
+
+fn field_i64(data: &[Value], index: usize) -> Result<i64, ParseError> {
+    match data.get(index).and_then(|v| v.as_i64()) {
+        Some(v) => Ok(v),
+        None => Err(invalid_field(data, index)),
+    }
+}
+
+fn invalid_field(data: &[Value], index: usize) -> ParseError {
+    let value = match data.get(index) {
+        Some(v) => v.to_string(),
+        None => "missing".to_string(),
+    };
+
+    ParseError::InvalidField(format!("data[{}]", index), value)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::values_store::app_config::ProviderKind;
+    use crate::data_analysis::trade::Trade;
+    use crate::data_parsers::tiingo_parser::{parse_tiingo_data, TiingoUpdate};
+    use crate::data_parsers::fixtures::{read_fixtures, fixture};
+
+    fn trades(json_data: &str, include_quotes: bool) -> Vec<Trade> {
+        match parse_tiingo_data(json_data, include_quotes).unwrap() {
+            TiingoUpdate::Trades(v) => v,
+            update => panic!("Expected trades, got {:?}", update),
+        }
+    }
+
+    #[test]
+    fn parse_tiingo_trade() {
+        let trades = trades(&fixture("tiingo/valid/trade.json"), false);
+
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].symbol, "TSM");
+        assert_eq!(trades[0].price.to_string(), "156.25");
+        assert_eq!(trades[0].size, 100);
+        assert_eq!(trades[0].timestamp_millis(), 1725636476438);
+        assert_eq!(trades[0].source, ProviderKind::Tiingo);
+        assert!(trades[0].is_valid());
+    }
+
+    #[test]
+    fn parse_tiingo_quote() {
+        let quote = fixture("tiingo/valid/quote.json");
+
+        assert!(trades(&quote, false).is_empty());
+
+        let trades = trades(&quote, true);
+
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].symbol, "TSM");
+        assert_eq!(trades[0].price.to_string(), "156.25");
+        assert_eq!(trades[0].size, 0);
+        assert_eq!(trades[0].timestamp_millis(), 1725636476500);
+        assert!(trades[0].is_valid());
+    }
+
+    #[test]
+    fn parse_tiingo_control_messages() {
+        assert_eq!(parse_tiingo_data(&fixture("tiingo/valid/heartbeat.json"), true).unwrap(), TiingoUpdate::Heartbeat);
+        assert_eq!(
+            parse_tiingo_data(&fixture("tiingo/valid/subscribed.json"), true).unwrap(),
+            TiingoUpdate::Info(r#"{"code":200,"message":"Success"}"#.to_string()),
+        );
+    }
+
+    #[test]
+    fn tiingo_fixture_corpus() {
+        for (name, frame) in read_fixtures("tiingo/valid") {
+            assert!(parse_tiingo_data(&frame, true).is_ok(), "{}", name);
+        }
+
+        for (name, frame) in read_fixtures("tiingo/invalid") {
+            assert!(parse_tiingo_data(&frame, true).is_err(), "{}", name);
+        }
+    }
+}
@@ -1,59 +1,90 @@
-use crate::data_analysis::finnhub_data_row::FinnhubDataRow;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::data_analysis::trade::Trade;
+use crate::data_analysis::price::Price;
+use crate::values_store::app_config::ProviderKind;
+use crate::data_parsers::parse_error::ParseError;
+
+#[derive(Deserialize)]
+struct TwelvePrice {
+    symbol: String,
+    #[serde(default)]
+    exchange: Option<String>,
+    timestamp: i64,
+    price: Price,
+    #[serde(default)]
+    day_volume: f64,
+}
+
+impl TryFrom<TwelvePrice> for Trade {
+    type Error = ParseError;
+
+    fn try_from(price: TwelvePrice) -> Result<Self, Self::Error> {
+        let timestamp = match Trade::timestamp_from_millis(price.timestamp.saturating_mul(1000)) {
+            Some(v) => v,
+            None => return Err(ParseError::InvalidField("timestamp".to_string(), price.timestamp.to_string())),
+        };
+
+        Ok(Trade {
+            symbol: price.symbol,
+            exchange: price.exchange,
+            price: price.price,
+            size: price.day_volume as i64,
+            timestamp,
+            conditions: Vec::new(),
+            source: ProviderKind::Twelve,
+            trade_id: None,
+        })
+    }
+}
 
 /*
-    Find the first occurence of data in string and parse the data from the given index
+    {"event":"price","symbol":"AAPL","exchange":"NASDAQ","timestamp":1725636476,"price":222.25,"day_volume":31240000}
+    The timestamp is in seconds. The size is the accumulated day volume, the difference to the
+    last update is calculated in StockAnalyserWeb.
 */
-pub fn parse_twelve_data(json_data: &String) -> FinnhubDataRow {
-    let json_chars = json_data.chars();
-
-    let mut open_brackets: i32 = 0;
-    let mut open_string: bool = false;
-
-    let mut tmp:String = String::new();
-    let mut key:String = String::new();
-
-    let mut current_datapoint:FinnhubDataRow = FinnhubDataRow::new();
-    
-    for c in json_chars.into_iter() {
-        let mut push_char: bool = false;
-
-        match c {
-            '\n' | '\r' | ' ' => (),
-            '"' => {
-                match open_string {
-                    true => open_brackets -= 1,
-                    false => open_brackets += 1,
-                };
-                
-                open_string = !open_string;
-            },
-            '{' | '[' => open_brackets += 1,
-            '}' | ']' => open_brackets -= 1,
-            ':' => {
-                match open_brackets {
-                    1 => {
-                        key = tmp;
-                        tmp = String::new();
-                    },
-                    _ => push_char = true,
-                }
-            },
-            ',' => {
-                if open_brackets <= 1 {
-                    current_datapoint.set_twelve_data(&key, &tmp);
-                    tmp.clear();
-                } else {
-                    push_char = true;
-                }
-            },
-            _ => push_char = true,
+pub fn parse_twelve_data(json_data: &str) -> Result<Vec<Trade>, ParseError> {
+    let message: Value = serde_json::from_str(json_data)?;
+
+    match message.get("event").and_then(|v| v.as_str()) {
+        Some("price") => (),
+        Some(_) => return Ok(Vec::new()),
+        None => return Err(ParseError::InvalidField("event".to_string(), message.to_string())),
+    };
+
+    let price: TwelvePrice = serde_json::from_value(message)?;
+
+    Ok(vec![Trade::try_from(price)?])
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::values_store::app_config::ProviderKind;
+    use crate::data_parsers::twelve_parser::parse_twelve_data;
+    use crate::data_parsers::fixtures::{read_fixtures, fixture};
+
+    #[test]
+    fn parse_twelve_price() {
+        let trades = parse_twelve_data(&fixture("twelve/valid/price.json")).unwrap();
+
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].symbol, "AAPL");
+        assert_eq!(trades[0].exchange, Some("NASDAQ".to_string()));
+        assert_eq!(trades[0].price.to_string(), "222.25");
+        assert_eq!(trades[0].size, 31240000);
+        assert_eq!(trades[0].timestamp_millis(), 1725636476000);
+        assert_eq!(trades[0].source, ProviderKind::Twelve);
+    }
+
+    #[test]
+    fn twelve_fixture_corpus() {
+        for (name, frame) in read_fixtures("twelve/valid") {
+            assert!(parse_twelve_data(&frame).is_ok(), "{}", name);
         }
 
-        if push_char {
-            tmp.push(c);
+        for (name, frame) in read_fixtures("twelve/invalid") {
+            assert!(parse_twelve_data(&frame).is_err(), "{}", name);
         }
     }
-
-    current_datapoint.set_twelve_data(&key, &tmp);
-    current_datapoint
-}
\ No newline at end of file
+}
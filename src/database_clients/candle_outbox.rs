@@ -0,0 +1,456 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    collections::VecDeque,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::database_clients::data_web_client::{DataTradeModel, stockdata_to_json};
+
+const LOG_FILE: &str = "candles.log";
+const ACK_FILE: &str = "candles.ack";
+const IN_FLIGHT_LIMIT: usize = 10_000;
+const ACK_TIMEOUT: Duration = Duration::from_secs(30);
+const COMPACT_OFFSET: u64 = 16 * 1024 * 1024;
+
+/*
+    Write-ahead log of the bars for the StockDatastore. <dir>/candles.log holds one
+    "sequence \t json" line per bar, <dir>/candles.ack the last sequence number the store
+    acknowledged and the offset of the line after it.
+
+    Bars are sent from the log in order and stay in it until the store acknowledged their
+    sequence number, so neither an outage nor a restart loses them and a bar sent twice
+    carries the same number both times. Once everything is acknowledged the log is emptied,
+    an acknowledged start longer than compact_offset bytes is cut off before that. Bars
+    without acknowledgement for ACK_TIMEOUT are sent again.
+*/
+pub struct CandleOutbox {
+    dir: PathBuf,
+    compact_offset: u64,
+    state: Mutex<OutboxState>,
+}
+
+struct OutboxState {
+    log: File,
+    log_length: u64,
+    next_sequence: u64,
+    acked_sequence: u64,
+    acked_offset: u64,
+    send_offset: u64,
+    in_flight: VecDeque<(u64, u64, Instant)>, //sequence, offset after its line, time sent
+}
+
+impl OutboxState {
+    fn rewind(&mut self) {
+        self.in_flight.clear();
+        self.send_offset = self.acked_offset;
+    }
+}
+
+impl CandleOutbox {
+    /*
+        A line cut off by a crash is removed, bars that were sent but not acknowledged
+        before the restart are sent again
+    */
+    pub fn open(dir: &str) -> io::Result<Self> {
+        let dir = PathBuf::from(dir);
+        fs::create_dir_all(&dir)?;
+
+        let (acked_sequence, acked_offset) = read_ack(&dir.join(ACK_FILE))?;
+
+        let log_path = dir.join(LOG_FILE);
+        let mut log = OpenOptions::new().read(true).append(true).create(true).open(&log_path)?;
+
+        let mut content: Vec<u8> = Vec::new();
+        log.read_to_end(&mut content)?;
+
+        let log_length = match content.iter().rposition(|v| *v == b'\n') {
+            Some(v) => v as u64 + 1,
+            None => 0,
+        };
+
+        if log_length < content.len() as u64 {
+            println!("Removing incomplete outbox line at the end of {}", log_path.display());
+            log.set_len(log_length)?;
+        }
+
+        let mut next_sequence = acked_sequence + 1;
+
+        for line in content[..log_length as usize].split(|v| *v == b'\n') {
+            if let Some((sequence, _)) = entry_from_line(&String::from_utf8_lossy(line)) {
+                next_sequence = next_sequence.max(sequence + 1);
+            }
+        }
+
+        let acked_offset = acked_offset.min(log_length);
+
+        Ok(CandleOutbox {
+            dir,
+            compact_offset: COMPACT_OFFSET,
+            state: Mutex::new(OutboxState {
+                log,
+                log_length,
+                next_sequence,
+                acked_sequence,
+                acked_offset,
+                send_offset: acked_offset,
+                in_flight: VecDeque::new(),
+            }),
+        })
+    }
+
+    /*
+        The bars are on disk once this returned Ok
+    */
+    pub fn append(&self, list_of_trades: &[DataTradeModel]) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let mut sequence = state.next_sequence;
+        let mut content = String::new();
+
+        for database_model in list_of_trades.iter() {
+            content.push_str(&format!("{}\t{}\n", sequence, stockdata_to_json(database_model, Some(sequence)).replace('\n', " ")));
+            sequence += 1;
+        }
+
+        if content.is_empty() {
+            return Ok(());
+        }
+
+        if let Err(e) = state.log.write_all(content.as_bytes()).and_then(|_| state.log.sync_data()) {
+            let log_length = state.log_length;
+            let _ = state.log.set_len(log_length);
+
+            return Err(e);
+        }
+
+        state.log_length += content.len() as u64;
+        state.next_sequence = sequence;
+
+        Ok(())
+    }
+
+    /*
+        The next bars to send, nothing while too many are waiting for their acknowledgement.
+        If the oldest bar sent is not acknowledged after ACK_TIMEOUT, everything without
+        acknowledgement is sent again.
+    */
+    pub fn next_batch(&self, max_entries: usize, now: Instant) -> io::Result<Vec<String>> {
+        let mut state = self.state.lock().unwrap();
+        let mut list_of_entries: Vec<String> = Vec::new();
+
+        if state.in_flight.front().is_some_and(|v| now.saturating_duration_since(v.2) >= ACK_TIMEOUT) {
+            println!("No acknowledgement from StockDatastore for {} seconds, sending the bars again", ACK_TIMEOUT.as_secs());
+
+            state.rewind();
+        }
+
+        if state.send_offset >= state.log_length || state.in_flight.len() >= IN_FLIGHT_LIMIT {
+            return Ok(list_of_entries);
+        }
+
+        let mut file = File::open(self.dir.join(LOG_FILE))?;
+        file.seek(SeekFrom::Start(state.send_offset))?;
+
+        let mut reader = BufReader::new(file.take(state.log_length - state.send_offset));
+        let mut line = String::new();
+
+        while list_of_entries.len() < max_entries && state.in_flight.len() < IN_FLIGHT_LIMIT {
+            line.clear();
+
+            let length = reader.read_line(&mut line)?;
+
+            if length == 0 {
+                break;
+            }
+
+            state.send_offset += length as u64;
+
+            // acknowledged lines are left over if a crash came before the log was emptied
+            match entry_from_line(line.trim_end_matches('\n')) {
+                Some((sequence, json)) if sequence > state.acked_sequence => {
+                    let send_offset = state.send_offset;
+
+                    state.in_flight.push_back((sequence, send_offset, now));
+                    list_of_entries.push(json);
+                },
+                _ => (),
+            };
+        }
+
+        Ok(list_of_entries)
+    }
+
+    /*
+        Acknowledges every bar up to and including sequence
+    */
+    pub fn ack(&self, sequence: u64) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+
+        while let Some((in_flight_sequence, offset, _)) = state.in_flight.front().copied() {
+            if in_flight_sequence > sequence {
+                break;
+            }
+
+            state.in_flight.pop_front();
+            state.acked_sequence = in_flight_sequence;
+            state.acked_offset = offset;
+        }
+
+        if state.in_flight.is_empty() && state.acked_offset == state.log_length && state.log_length > 0 {
+            write_ack(&self.dir, state.acked_sequence, 0)?;
+
+            state.log.set_len(0)?;
+            state.log_length = 0;
+            state.acked_offset = 0;
+            state.send_offset = 0;
+
+            return Ok(());
+        }
+
+        if state.acked_offset >= self.compact_offset {
+            return self.compact(&mut state);
+        }
+
+        write_ack(&self.dir, state.acked_sequence, state.acked_offset)
+    }
+
+    /*
+        Replaces the log by its part without acknowledgement. The acknowledgement points at
+        the start before the log is replaced, a crash in between only leaves acknowledged
+        lines which are skipped.
+    */
+    fn compact(&self, state: &mut OutboxState) -> io::Result<()> {
+        let log_path = self.dir.join(LOG_FILE);
+        let tmp_path = self.dir.join(format!("{}.tmp", LOG_FILE));
+
+        let mut file = File::open(&log_path)?;
+        file.seek(SeekFrom::Start(state.acked_offset))?;
+
+        let mut tmp = File::create(&tmp_path)?;
+        io::copy(&mut file.take(state.log_length - state.acked_offset), &mut tmp)?;
+        tmp.sync_all()?;
+
+        write_ack(&self.dir, state.acked_sequence, 0)?;
+
+        fs::rename(&tmp_path, &log_path)?;
+        File::open(&self.dir)?.sync_all()?;
+
+        let acked_offset = state.acked_offset;
+
+        state.log = OpenOptions::new().read(true).append(true).open(&log_path)?;
+        state.log_length -= acked_offset;
+        state.send_offset -= acked_offset;
+        state.acked_offset = 0;
+
+        for (_, offset, _) in state.in_flight.iter_mut() {
+            *offset -= acked_offset;
+        }
+
+        Ok(())
+    }
+
+    /*
+        After a lost connection every bar without acknowledgement is sent again
+    */
+    pub fn rewind(&self) {
+        self.state.lock().unwrap().rewind();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let state = self.state.lock().unwrap();
+
+        state.acked_sequence + 1 >= state.next_sequence
+    }
+}
+
+fn entry_from_line(line: &str) -> Option<(u64, String)> {
+    let (sequence, json) = line.split_once('\t')?;
+
+    Some((sequence.parse::<u64>().ok()?, json.to_string()))
+}
+
+fn read_ack(path: &Path) -> io::Result<(u64, u64)> {
+    let content = match fs::read_to_string(path) {
+        Ok(v) => v,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok((0, 0)),
+        Err(e) => return Err(e),
+    };
+
+    let mut fields = content.split_whitespace().map(|v| v.parse::<u64>());
+
+    match (fields.next(), fields.next()) {
+        (Some(Ok(sequence)), Some(Ok(offset))) => Ok((sequence, offset)),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidData, format!("Invalid outbox acknowledgement in {}", path.display()))),
+    }
+}
+
+/*
+    Replaced in one step, so a crash leaves either the old or the new acknowledgement
+*/
+fn write_ack(dir: &Path, sequence: u64, offset: u64) -> io::Result<()> {
+    let tmp_path = dir.join(format!("{}.tmp", ACK_FILE));
+
+    let mut tmp = File::create(&tmp_path)?;
+    tmp.write_all(format!("{} {}\n", sequence, offset).as_bytes())?;
+    tmp.sync_all()?;
+
+    fs::rename(&tmp_path, dir.join(ACK_FILE))?;
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::time::{Duration, Instant};
+
+    use serde_json::Value;
+
+    use crate::data_analysis::price::Price;
+    use crate::database_clients::data_web_client::DataTradeModel;
+    use crate::database_clients::candle_outbox::{CandleOutbox, LOG_FILE, ACK_TIMEOUT};
+
+    fn outbox_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("stockwatch-outbox-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        dir
+    }
+
+    fn candle(timestamp: i64) -> DataTradeModel {
+        DataTradeModel {
+            timestamp,
+            stock_name: "AAPL".to_string(),
+            stock_interval: 1,
+            price_decimals: 2,
+            open_price: Price::from_f64(10.0).unwrap(),
+            high_price: Price::from_f64(10.0).unwrap(),
+            low_price: Price::from_f64(10.0).unwrap(),
+            close_price: Price::from_f64(10.0).unwrap(),
+            vwap: Price::from_f64(10.0).unwrap(),
+            volume_moved: 0,
+            num_of_trades: 0,
+        }
+    }
+
+    fn sequences(candle_outbox: &CandleOutbox) -> Vec<u64> {
+        sequences_at(candle_outbox, Instant::now())
+    }
+
+    fn sequences_at(candle_outbox: &CandleOutbox, now: Instant) -> Vec<u64> {
+        candle_outbox.next_batch(100, now).unwrap().iter().map(|v| {
+            let json: Value = serde_json::from_str(v).unwrap();
+
+            json["sq"].as_u64().unwrap()
+        }).collect()
+    }
+
+    #[test]
+    fn unacknowledged_bars_are_sent_again() {
+        let dir = outbox_dir("resend");
+        let candle_outbox = CandleOutbox::open(dir.to_str().unwrap()).unwrap();
+
+        candle_outbox.append(&[candle(1_000), candle(2_000), candle(3_000)]).unwrap();
+
+        assert_eq!(sequences(&candle_outbox), vec![1, 2, 3]);
+        assert_eq!(sequences(&candle_outbox), Vec::<u64>::new());
+
+        candle_outbox.ack(2).unwrap();
+        candle_outbox.rewind();
+
+        assert_eq!(sequences(&candle_outbox), vec![3]);
+        assert!(!candle_outbox.is_empty());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn bars_survive_a_restart() {
+        let dir = outbox_dir("restart");
+        let candle_outbox = CandleOutbox::open(dir.to_str().unwrap()).unwrap();
+
+        candle_outbox.append(&[candle(1_000), candle(2_000), candle(3_000)]).unwrap();
+        assert_eq!(sequences(&candle_outbox), vec![1, 2, 3]);
+        candle_outbox.ack(1).unwrap();
+        drop(candle_outbox);
+
+        let mut log = OpenOptions::new().append(true).open(dir.join(LOG_FILE)).unwrap();
+        write!(log, "4\t{{\"si\": 1,").unwrap();
+
+        let candle_outbox = CandleOutbox::open(dir.to_str().unwrap()).unwrap();
+        candle_outbox.append(&[candle(4_000)]).unwrap();
+
+        assert_eq!(sequences(&candle_outbox), vec![2, 3, 4]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn log_is_emptied_once_everything_is_acknowledged() {
+        let dir = outbox_dir("empty");
+        let candle_outbox = CandleOutbox::open(dir.to_str().unwrap()).unwrap();
+
+        candle_outbox.append(&[candle(1_000), candle(2_000)]).unwrap();
+        assert_eq!(sequences(&candle_outbox), vec![1, 2]);
+        candle_outbox.ack(2).unwrap();
+
+        assert!(candle_outbox.is_empty());
+        assert_eq!(fs::metadata(dir.join(LOG_FILE)).unwrap().len(), 0);
+        drop(candle_outbox);
+
+        let candle_outbox = CandleOutbox::open(dir.to_str().unwrap()).unwrap();
+        candle_outbox.append(&[candle(3_000)]).unwrap();
+
+        assert_eq!(sequences(&candle_outbox), vec![3]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn bars_without_acknowledgement_are_sent_again_after_the_timeout() {
+        let dir = outbox_dir("timeout");
+        let candle_outbox = CandleOutbox::open(dir.to_str().unwrap()).unwrap();
+        let start = Instant::now();
+
+        candle_outbox.append(&[candle(1_000), candle(2_000)]).unwrap();
+        assert_eq!(sequences_at(&candle_outbox, start), vec![1, 2]);
+
+        candle_outbox.ack(1).unwrap();
+
+        assert_eq!(sequences_at(&candle_outbox, start + ACK_TIMEOUT - Duration::from_secs(1)), Vec::<u64>::new());
+        assert_eq!(sequences_at(&candle_outbox, start + ACK_TIMEOUT), vec![2]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn acknowledged_start_is_cut_off() {
+        let dir = outbox_dir("compact");
+        let mut candle_outbox = CandleOutbox::open(dir.to_str().unwrap()).unwrap();
+        candle_outbox.compact_offset = 1;
+
+        candle_outbox.append(&[candle(1_000), candle(2_000), candle(3_000)]).unwrap();
+        assert_eq!(sequences(&candle_outbox), vec![1, 2, 3]);
+
+        let log_length = fs::metadata(dir.join(LOG_FILE)).unwrap().len();
+        candle_outbox.ack(1).unwrap();
+
+        assert!(fs::metadata(dir.join(LOG_FILE)).unwrap().len() < log_length);
+
+        candle_outbox.append(&[candle(4_000)]).unwrap();
+        candle_outbox.ack(2).unwrap();
+        candle_outbox.rewind();
+
+        assert_eq!(sequences(&candle_outbox), vec![3, 4]);
+        drop(candle_outbox);
+
+        let candle_outbox = CandleOutbox::open(dir.to_str().unwrap()).unwrap();
+
+        assert_eq!(sequences(&candle_outbox), vec![3, 4]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,33 @@
+use std::fmt;
+use std::error;
+
+/*
+    The StockDatastore could not be reached or did not answer with the symbol list
+*/
+#[derive(Debug)]
+pub enum DataStoreError {
+    Connect(Box<tungstenite::Error>),
+    Receive(Box<tungstenite::Error>),
+    UnexpectedMessage(String),
+    Closed,
+}
+
+impl fmt::Display for DataStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DataStoreError::Connect(e) => write!(f, "Error connecting to StockDatastore: {}", e),
+            DataStoreError::Receive(e) => write!(f, "Error receiving the symbol list: {}", e),
+            DataStoreError::UnexpectedMessage(kind) => write!(f, "Expected the symbol list, received a {} message", kind),
+            DataStoreError::Closed => write!(f, "StockDatastore closed the connection before sending the symbol list"),
+        }
+    }
+}
+
+impl error::Error for DataStoreError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            DataStoreError::Connect(e) | DataStoreError::Receive(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
@@ -1,78 +1,281 @@
 use std::{ 
     thread, 
     net::TcpStream, 
-    time::Duration,
+    io::ErrorKind,
+    time::{Duration, Instant},
     collections::VecDeque,
-    sync::{RwLock, Arc}
+    sync::{Arc, Mutex, Condvar, atomic::{AtomicU64, Ordering}}
 };
 
 use tungstenite::{
     connect,
+    Error,
     Message,
     WebSocket,
     stream::MaybeTlsStream
 };
 
+use crate::data_analysis::price::Price;
+use crate::values_store::app_config::{OverflowPolicy, WireEncoding};
+use crate::database_clients::candle_outbox::CandleOutbox;
+use crate::database_clients::data_store_error::DataStoreError;
+use crate::database_clients::wire_format::{WireMessage, encode_json};
+use crate::web_clients::session_driver::set_read_timeout;
 
+
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+#[derive(Debug, PartialEq)]
 pub struct DataTradeModel {
     pub timestamp:i64,
     pub stock_name: String,
     pub stock_interval: usize,
+    pub price_decimals: u32,
 
-    pub avg_price: f64,
-    pub avg_price_open: f64,
-    pub min_price: f64,
-    pub max_price: f64,
+    pub open_price: Price,
+    pub high_price: Price,
+    pub low_price: Price,
+    pub close_price: Price,
+    pub vwap: Price,
 
     pub volume_moved: i64,
     pub num_of_trades: i64,
 }
 
+/*
+    A bar waiting for the StockDatastore, symbol, interval and start are kept for coalescing
+*/
+struct QueuedCandle {
+    stock_name: String,
+    stock_interval: usize,
+    timestamp: i64,
+    json: String,
+}
+
+impl QueuedCandle {
+    fn same_bar(&self, other: &QueuedCandle) -> bool {
+        self.stock_name == other.stock_name && self.stock_interval == other.stock_interval && self.timestamp == other.timestamp
+    }
+}
+
+/*
+    Bounded queue between the candle thread and the connection to the StockDatastore.
+    A bar only leaves it once it was sent, so it survives a reconnect.
+*/
+struct Outbox {
+    queue: Mutex<VecDeque<QueuedCandle>>,
+    space: Condvar,
+    capacity: usize,
+    overflow_policy: OverflowPolicy,
+    dropped_candles: AtomicU64,
+}
+
+impl Outbox {
+    /*
+        With coalescing an amended bar replaces the queued version of the same bar, any
+        other bar drops the oldest one like drop-oldest
+    */
+    fn push(&self, candle: QueuedCandle) {
+        let mut queue = self.queue.lock().unwrap();
+
+        if self.overflow_policy == OverflowPolicy::Coalesce && queue.len() >= self.capacity {
+            if let Some(queued) = queue.iter_mut().find(|v| v.same_bar(&candle)) {
+                *queued = candle;
+                self.count_dropped();
+
+                return;
+            }
+        }
+
+        while queue.len() >= self.capacity {
+            match self.overflow_policy {
+                OverflowPolicy::Block => queue = self.space.wait(queue).unwrap(),
+                OverflowPolicy::DropOldest | OverflowPolicy::Coalesce => {
+                    queue.pop_front();
+                    self.count_dropped();
+                },
+            };
+        }
+
+        queue.push_back(candle);
+    }
+
+    fn take(&self) -> Option<QueuedCandle> {
+        let candle = self.queue.lock().unwrap().pop_front();
+
+        if candle.is_some() {
+            self.space.notify_all();
+        }
+
+        candle
+    }
+
+    /*
+        A bar that could not be sent goes back to the front, even if the queue is full
+    */
+    fn put_back(&self, candle: QueuedCandle) {
+        self.queue.lock().unwrap().push_front(candle);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queue.lock().unwrap().is_empty()
+    }
+
+    fn count_dropped(&self) {
+        let dropped_candles = self.dropped_candles.fetch_add(1, Ordering::Relaxed) + 1;
+
+        if dropped_candles == 1 || dropped_candles.is_multiple_of(1000) {
+            println!("StockDatastore queue is full, {} bars dropped so far", dropped_candles);
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct DataWebClient {
     addr: String,
-    update_queue: Arc<RwLock<VecDeque<String>>>,
+    outbox: Arc<Outbox>,
+    candle_outbox: Option<Arc<CandleOutbox>>,
+    encoding: WireEncoding,
 }
 
 impl DataWebClient {
-    pub fn new(addr: &str) -> Self {
-        let update_queue = Arc::new(RwLock::new(VecDeque::new()));
+    pub fn new(addr: &str, capacity: usize, overflow_policy: OverflowPolicy) -> Self {
+        let outbox = Arc::new(Outbox {
+            queue: Mutex::new(VecDeque::new()),
+            space: Condvar::new(),
+            capacity: capacity.max(1),
+            overflow_policy,
+            dropped_candles: AtomicU64::new(0),
+        });
 
-        DataWebClient{ addr: addr.to_owned(), update_queue: update_queue }
+        DataWebClient{ addr: addr.to_owned(), outbox, candle_outbox: None, encoding: WireEncoding::Json }
     }
 
-    pub fn add_finnhub_data(&mut self, list_of_trades:Vec<DataTradeModel>) {
-        while self.update_queue.read().unwrap().len() > 0 {
-            let _ = self.update_queue.write().unwrap().pop_front();
-        }
+    /*
+        Other encodings than json are asked for with "encoding=<name>" in the query of the
+        address. Has to be set before the client is cloned or started.
+    */
+    pub fn set_encoding(&mut self, encoding: WireEncoding) {
+        self.encoding = encoding;
+    }
+
+    /*
+        Bars go through the outbox on disk and carry a sequence number the StockDatastore
+        acknowledges. Has to be set before the client is cloned or started.
+    */
+    pub fn set_candle_outbox(&mut self, candle_outbox: CandleOutbox) {
+        self.candle_outbox = Some(Arc::new(candle_outbox));
+    }
+
+    /*
+        Blocks while the queue is full if the overflow policy says so. With an outbox on
+        disk the queue only takes the bars the outbox failed to write.
+    */
+    pub fn add_candles(&mut self, list_of_trades:Vec<DataTradeModel>) {
+        let list_of_trades = match &self.candle_outbox {
+            Some(candle_outbox) => match candle_outbox.append(&list_of_trades) {
+                Ok(()) => return,
+                Err(e) => {
+                    println!("Error writing candles to the outbox: {}", e);
+                    list_of_trades
+                },
+            },
+            None => list_of_trades,
+        };
 
         for database_model in list_of_trades.into_iter() {
-            self.update_queue.write().unwrap().push_back(stockdata_to_json(database_model));
+            self.outbox.push(QueuedCandle {
+                stock_name: database_model.stock_name.clone(),
+                stock_interval: database_model.stock_interval,
+                timestamp: database_model.timestamp,
+                json: stockdata_to_json(&database_model, None),
+            });
         }
     }
 
-    pub fn start_client(&self) -> Vec<String> {
-        let (mut client, _response) = connect(&self.addr).unwrap();
-        let stock_list = init_client(&mut client);
-        
-        let addr_clone = self.addr.clone();
-        let update_queue_clone = self.update_queue.clone();
+    /*
+        Bars thrown away or replaced by an amended version because the queue was full
+    */
+    pub fn dropped_candles(&self) -> u64 {
+        self.outbox.dropped_candles.load(Ordering::Relaxed)
+    }
+
+    /*
+        Waits until the queue is empty or the timeout passed, true if everything was sent
+    */
+    pub fn wait_until_sent(&self, timeout: Duration) -> bool {
+        let start_time = Instant::now();
+
+        while !self.outbox.is_empty() || self.candle_outbox.as_ref().is_some_and(|v| !v.is_empty()) {
+            if start_time.elapsed() >= timeout {
+                return false;
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        true
+    }
+
+    /*
+        Connects with up to startup_attempts tries, waiting twice as long after every failed
+        one. The bars are sent on a thread that keeps reconnecting the same way, also when
+        the StockDatastore could not be reached at startup.
+    */
+    pub fn start_client(&self, startup_attempts: u32) -> Result<Vec<String>, DataStoreError> {
+        let mut retry_delay = Duration::from_secs(1);
+        let mut attempt: u32 = 1;
+        let addr = address_with_encoding(&self.addr, self.encoding);
+
+        let (client, stock_list) = loop {
+            match open_connection(&addr) {
+                Ok((client, stock_list)) => break (Some(client), Ok(stock_list)),
+                Err(e) if attempt < startup_attempts => {
+                    println!("{}, retrying in {} seconds", e, retry_delay.as_secs());
+
+                    thread::sleep(retry_delay);
+
+                    retry_delay = (retry_delay * 2).min(MAX_RETRY_DELAY);
+                    attempt += 1;
+                },
+                Err(e) => break (None, Err(e)),
+            };
+        };
+
+        let addr_clone = addr.clone();
+        let outbox_clone = self.outbox.clone();
+        let candle_outbox_clone = self.candle_outbox.clone();
+        let encoding = self.encoding;
 
         thread::spawn(move || {
+            let mut client = client;
+            let mut retry_delay = Duration::from_secs(1);
+
             loop {
-                update_polling(&mut client, &update_queue_clone);
+                // the lost connection is dropped before a new one is opened
+                if let Some(mut client) = client.take() {
+                    match &candle_outbox_clone {
+                        Some(candle_outbox) => outbox_polling(&mut client, &outbox_clone, candle_outbox, encoding),
+                        None => update_polling(&mut client, &outbox_clone, encoding),
+                    };
+                }
 
-                thread::sleep(Duration::from_millis(1000));
+                thread::sleep(retry_delay);
 
-                client = match connect(&addr_clone) {
-                    Ok((c, _r)) => c,
-                    Err(_) => {
-                        println!("Error connecting to StockDatastore");
+                client = match open_connection(&addr_clone) {
+                    Ok((c, _stock_list)) => {
+                        retry_delay = Duration::from_secs(1);
 
-                        continue;
+                        Some(c)
                     },
-                };
+                    Err(e) => {
+                        retry_delay = (retry_delay * 2).min(MAX_RETRY_DELAY);
+
+                        println!("{}, retrying in {} seconds", e, retry_delay.as_secs());
 
-                let _ = init_client(&mut client);
+                        None
+                    },
+                };
             }
         });
 
@@ -80,45 +283,159 @@ impl DataWebClient {
     }
 }
 
-fn init_client(client: &mut WebSocket<MaybeTlsStream<TcpStream>>) -> Vec<String> {
-    let msg = match client.read() {
-        Ok(p) => p,
-        Err(e) => {
-            panic!("Error receiving message {} \n Closing Client", e);
-        },
+/*
+    ws://localhost:9003 becomes ws://localhost:9003/?encoding=msgpack, json keeps the address
+*/
+fn address_with_encoding(addr: &str, encoding: WireEncoding) -> String {
+    let host_start = addr.find("://").map(|v| v + 3).unwrap_or(0);
+    let has_path = addr[host_start..].contains('/');
+
+    match (encoding, addr.contains('?'), has_path) {
+        (WireEncoding::Json, _, _) => addr.to_string(),
+        (_, true, _) => format!("{}&encoding={}", addr, encoding),
+        (_, false, true) => format!("{}?encoding={}", addr, encoding),
+        (_, false, false) => format!("{}/?encoding={}", addr, encoding),
+    }
+}
+
+/*
+    The StockDatastore greets every connection with its symbols, "AAPL|MSFT|..."
+*/
+fn open_connection(addr: &str) -> Result<(WebSocket<MaybeTlsStream<TcpStream>>, Vec<String>), DataStoreError> {
+    let (mut client, _response) = match connect(addr) {
+        Ok(v) => v,
+        Err(e) => return Err(DataStoreError::Connect(Box::new(e))),
     };
 
-    match msg {
-        msg @ Message::Text(_) => {
-            let text: String = msg.into_text().unwrap();
+    let stock_list = init_client(&mut client)?;
+
+    Ok((client, stock_list))
+}
 
-            text.split('|').map(|s| s.to_string()).filter(|s| s.len() > 0).collect()
+fn init_client(client: &mut WebSocket<MaybeTlsStream<TcpStream>>) -> Result<Vec<String>, DataStoreError> {
+    loop {
+        let msg = match client.read() {
+            Ok(p) => p,
+            Err(e) => return Err(DataStoreError::Receive(Box::new(e))),
+        };
+
+        match msg {
+            Message::Text(text) => return Ok(text.split('|').map(|s| s.to_string()).filter(|s| !s.is_empty()).collect()),
+            Message::Ping(_) | Message::Pong(_) => (),
+            Message::Binary(_) => return Err(DataStoreError::UnexpectedMessage("binary".to_string())),
+            Message::Close(_) => return Err(DataStoreError::Closed),
+            Message::Frame(_) => return Err(DataStoreError::UnexpectedMessage("raw frame".to_string())),
+        };
+    }
+}
+
+/*
+    Sends the queued bars and reads in between so pings are answered and a close is noticed.
+    Returns when the connection is lost.
+*/
+fn update_polling(client: &mut WebSocket<MaybeTlsStream<TcpStream>>, outbox: &Outbox, encoding: WireEncoding) {
+    set_read_timeout(client, Duration::from_millis(5));
+
+    loop {
+        while let Some(update) = outbox.take() {
+            if let Err(e) = client.send(encode_json(&update.json, encoding)) {
+                println!("Error sending Message {}", e);
+
+                outbox.put_back(update);
+
+                return;
+            }
         }
-        _ => {
-            panic!("Received wrong message");
+
+        if !read_control(client) {
+            return;
+        }
+    }
+}
+
+/*
+    Reads what the StockDatastore sent without acknowledgements in mind, false once the
+    connection is closed
+*/
+fn read_control(client: &mut WebSocket<MaybeTlsStream<TcpStream>>) -> bool {
+    match client.read() {
+        Ok(Message::Close(_)) => {
+            println!("StockDatastore closed the connection");
+
+            false
+        },
+        Ok(Message::Text(text)) => {
+            println!("Unexpected message from StockDatastore: {}", text);
+
+            true
+        },
+        Ok(_) => true,
+        Err(Error::Io(ref error)) if error.kind() == ErrorKind::WouldBlock || error.kind() == ErrorKind::TimedOut => true,
+        Err(e) => {
+            println!("Error receiving message {}", e);
+
+            false
         },
     }
 }
 
-fn update_polling(client: &mut WebSocket<MaybeTlsStream<TcpStream>>, update_queue: &Arc<RwLock<VecDeque<String>>>) {
+/*
+    Sends the bars of the outbox and reads the acknowledgements of the StockDatastore,
+    {"ack": sequence} for every bar up to sequence. Returns when the connection is lost.
+*/
+fn outbox_polling(client: &mut WebSocket<MaybeTlsStream<TcpStream>>, outbox: &Outbox, candle_outbox: &CandleOutbox, encoding: WireEncoding) {
+    set_read_timeout(client, Duration::from_millis(5));
+
     loop {
-        let update = update_queue.write().unwrap().pop_front();
+        let list_of_entries = match candle_outbox.next_batch(100, Instant::now()) {
+            Ok(v) => v,
+            Err(e) => {
+                println!("Error reading the candle outbox {}", e);
+                Vec::new()
+            },
+        };
+
+        for entry in list_of_entries.iter() {
+            if let Err(e) = client.send(encode_json(entry, encoding)) {
+                println!("Error sending Message {}", e);
+
+                candle_outbox.rewind();
+
+                return;
+            }
+        }
+
+        while let Some(update) = outbox.take() {
+            if let Err(e) = client.send(encode_json(&update.json, encoding)) {
+                println!("Error sending Message {}", e);
 
-        let update = match update {
-            Some(v) => v,
-            None => {
-                thread::sleep(Duration::from_millis(5));
+                outbox.put_back(update);
+                candle_outbox.rewind();
 
-                continue;
+                return;
+            }
+        }
+
+        match client.read() {
+            Ok(Message::Text(text)) => match parse_ack(&text) {
+                Some(sequence) => if let Err(e) = candle_outbox.ack(sequence) {
+                    println!("Error writing the outbox acknowledgement {}", e);
+                },
+                None => println!("Unexpected message from StockDatastore: {}", text),
             },
-        };
+            Ok(Message::Close(_)) => {
+                println!("StockDatastore closed the connection");
 
-        match client.send(Message::text(&update)){
-            Ok(v) => v,
+                candle_outbox.rewind();
+
+                return;
+            },
+            Ok(_) => (),
+            Err(Error::Io(ref error)) if error.kind() == ErrorKind::WouldBlock || error.kind() == ErrorKind::TimedOut => (),
             Err(e) => {
-                println!("Error sending Message {}", e);
+                println!("Error receiving message {}", e);
 
-                update_queue.write().unwrap().push_front(update);
+                candle_outbox.rewind();
 
                 return;
             },
@@ -126,27 +443,301 @@ fn update_polling(client: &mut WebSocket<MaybeTlsStream<TcpStream>>, update_queu
     }
 }
 
-fn stockdata_to_json(update: DataTradeModel) -> String {
-    format!("{{
-            \"si\": {},
-            \"sn\": \"{}\",
-            \"ap\": \"{:.6}\",
-            \"op\": {:.6},
-            \"mn\": {:.6},
-            \"mx\": {:.6},
-            \"vm\": {},
-            \"nt\": {},
-            \"t\": {}
-        }}",
-        update.stock_interval,
-        update.stock_name,
-        update.avg_price / 100.0,
-        update.avg_price_open / 100.0,
-        update.min_price / 100.0,
-        update.max_price / 100.0,
-        update.volume_moved,
-        update.num_of_trades,
-        update.timestamp,
-    )
+fn parse_ack(text: &str) -> Option<u64> {
+    serde_json::from_str::<serde_json::Value>(text).ok()?.get("ack")?.as_u64()
+}
+
+/*
+    A bar in the wire format, sq is the sequence number of bars from the outbox on disk
+*/
+pub fn stockdata_to_json(update: &DataTradeModel, sequence: Option<u64>) -> String {
+    WireMessage::bar(update, sequence).to_json()
 }
 
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Duration;
+
+    use serde_json::Value;
+    use tungstenite::{accept, accept_hdr, Message};
+
+    use crate::data_analysis::price::Price;
+    use crate::values_store::app_config::{OverflowPolicy, WireEncoding};
+    use crate::database_clients::candle_outbox::CandleOutbox;
+    use crate::database_clients::data_store_error::DataStoreError;
+    use crate::database_clients::data_web_client::{DataWebClient, DataTradeModel, stockdata_to_json};
+    use crate::database_clients::trade_web_server::QueryCallback;
+    use crate::database_clients::wire_format::WireMessage;
+
+    fn price(value: f64) -> Price {
+        Price::from_f64(value).unwrap()
+    }
+
+    fn candle(stock_name: &str, stock_interval: usize, timestamp: i64) -> DataTradeModel {
+        DataTradeModel {
+            timestamp,
+            stock_name: stock_name.to_string(),
+            stock_interval,
+            price_decimals: 2,
+            open_price: price(10.0),
+            high_price: price(10.0),
+            low_price: price(10.0),
+            close_price: price(10.0),
+            vwap: price(10.0),
+            volume_moved: 0,
+            num_of_trades: 0,
+        }
+    }
+
+    fn queued(data_web_client: &DataWebClient) -> Vec<(String, usize, i64)> {
+        data_web_client.outbox.queue.lock().unwrap().iter().map(|v| {
+            let json: Value = serde_json::from_str(&v.json).unwrap();
+
+            (v.stock_name.clone(), v.stock_interval, json["t"].as_i64().unwrap())
+        }).collect()
+    }
+
+    #[test]
+    fn bars_are_kept_until_sent() {
+        let mut data_web_client = DataWebClient::new("ws://127.0.0.1:9", 10, OverflowPolicy::DropOldest);
+
+        data_web_client.add_candles(vec![candle("AAPL", 1, 1_000), candle("AAPL", 1, 2_000)]);
+        data_web_client.add_candles(vec![candle("AAPL", 1, 3_000)]);
+
+        let update = data_web_client.outbox.take().unwrap();
+        data_web_client.outbox.put_back(update);
+
+        assert_eq!(queued(&data_web_client), vec![
+            ("AAPL".to_string(), 1, 1_000),
+            ("AAPL".to_string(), 1, 2_000),
+            ("AAPL".to_string(), 1, 3_000),
+        ]);
+        assert_eq!(data_web_client.dropped_candles(), 0);
+    }
+
+    #[test]
+    fn full_queue_drops_the_oldest() {
+        let mut data_web_client = DataWebClient::new("ws://127.0.0.1:9", 2, OverflowPolicy::DropOldest);
+
+        data_web_client.add_candles(vec![candle("AAPL", 1, 1_000), candle("MSFT", 1, 1_000), candle("AAPL", 1, 2_000)]);
+
+        assert_eq!(queued(&data_web_client), vec![("MSFT".to_string(), 1, 1_000), ("AAPL".to_string(), 1, 2_000)]);
+        assert_eq!(data_web_client.dropped_candles(), 1);
+    }
+
+    #[test]
+    fn full_queue_coalesces_amended_bars() {
+        let mut data_web_client = DataWebClient::new("ws://127.0.0.1:9", 3, OverflowPolicy::Coalesce);
+
+        data_web_client.add_candles(vec![candle("AAPL", 1, 1_000), candle("MSFT", 1, 1_000), candle("AAPL", 60, 0)]);
+        data_web_client.add_candles(vec![DataTradeModel { close_price: price(11.0), ..candle("MSFT", 1, 1_000) }]);
+
+        // the amended bar replaced its queued version, no other bar went missing
+        assert_eq!(queued(&data_web_client), vec![
+            ("AAPL".to_string(), 1, 1_000),
+            ("MSFT".to_string(), 1, 1_000),
+            ("AAPL".to_string(), 60, 0),
+        ]);
+        assert!(data_web_client.outbox.queue.lock().unwrap()[1].json.contains("\"cp\":\"11.00\""));
+        assert_eq!(data_web_client.dropped_candles(), 1);
+
+        data_web_client.add_candles(vec![candle("MSFT", 1, 2_000)]);
+
+        assert_eq!(queued(&data_web_client), vec![
+            ("MSFT".to_string(), 1, 1_000),
+            ("AAPL".to_string(), 60, 0),
+            ("MSFT".to_string(), 1, 2_000),
+        ]);
+        assert_eq!(data_web_client.dropped_candles(), 2);
+    }
+
+    #[test]
+    fn full_queue_blocks_until_a_bar_is_sent() {
+        let mut data_web_client = DataWebClient::new("ws://127.0.0.1:9", 1, OverflowPolicy::Block);
+        let sender = data_web_client.clone();
+
+        data_web_client.add_candles(vec![candle("AAPL", 1, 1_000)]);
+
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            sender.outbox.take().unwrap()
+        });
+
+        data_web_client.add_candles(vec![candle("AAPL", 1, 2_000)]);
+
+        assert_eq!(handle.join().unwrap().stock_name, "AAPL");
+        assert_eq!(queued(&data_web_client), vec![("AAPL".to_string(), 1, 2_000)]);
+        assert_eq!(data_web_client.dropped_candles(), 0);
+    }
+
+    #[test]
+    fn candle_json_carries_ohlcv() {
+        let json: Value = serde_json::from_str(&stockdata_to_json(&DataTradeModel {
+            timestamp: 1_725_636_420_000,
+            stock_name: "AAPL".to_string(),
+            stock_interval: 60,
+            price_decimals: 2,
+            open_price: price(10.0),
+            high_price: price(12.0),
+            low_price: price(9.0),
+            close_price: price(11.25),
+            vwap: price(10.678),
+            volume_moved: 700,
+            num_of_trades: 6,
+        }, None)).unwrap();
+
+        assert_eq!(json["type"], "bar");
+        assert_eq!(json["si"], 60);
+        assert_eq!(json["sn"], "AAPL");
+        assert_eq!(json["op"], "10.00");
+        assert_eq!(json["mx"], "12.00");
+        assert_eq!(json["mn"], "9.00");
+        assert_eq!(json["cp"], "11.25");
+        assert_eq!(json["ap"], "10.68");
+        assert_eq!(json["vm"], 700);
+        assert_eq!(json["nt"], 6);
+        assert_eq!(json["t"], 1_725_636_420_000_i64);
+    }
+
+    #[test]
+    fn outbox_waits_for_the_acknowledgement() {
+        let dir = std::env::temp_dir().join(format!("stockwatch-outbox-client-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = format!("ws://{}", listener.local_addr().unwrap());
+
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket = accept(stream).unwrap();
+
+            socket.send(Message::text("AAPL|MSFT|")).unwrap();
+
+            let mut list_of_sequences: Vec<u64> = Vec::new();
+
+            while list_of_sequences.len() < 2 {
+                if let Message::Text(text) = socket.read().unwrap() {
+                    let json: Value = serde_json::from_str(&text).unwrap();
+                    list_of_sequences.push(json["sq"].as_u64().unwrap());
+                }
+            }
+
+            socket.send(Message::text("{\"ack\": 2}")).unwrap();
+            thread::sleep(Duration::from_millis(500));
+
+            list_of_sequences
+        });
+
+        let mut data_web_client = DataWebClient::new(&addr, 10, OverflowPolicy::DropOldest);
+        data_web_client.set_candle_outbox(CandleOutbox::open(dir.to_str().unwrap()).unwrap());
+        data_web_client.add_candles(vec![candle("AAPL", 1, 1_000), candle("MSFT", 1, 1_000)]);
+
+        assert!(!data_web_client.wait_until_sent(Duration::from_millis(10)));
+        assert_eq!(data_web_client.start_client(1).unwrap(), vec!["AAPL".to_string(), "MSFT".to_string()]);
+        assert!(data_web_client.wait_until_sent(Duration::from_secs(5)));
+        assert_eq!(server.join().unwrap(), vec![1, 2]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn bars_go_out_in_the_chosen_encoding() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = format!("ws://{}", listener.local_addr().unwrap());
+
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut query: Option<String> = None;
+
+            let mut socket = accept_hdr(stream, QueryCallback { query: &mut query }).unwrap();
+
+            socket.send(Message::text("AAPL|")).unwrap();
+
+            loop {
+                if let Message::Binary(payload) = socket.read().unwrap() {
+                    return (query, rmp_serde::from_slice::<WireMessage>(&payload).unwrap());
+                }
+            }
+        });
+
+        let mut data_web_client = DataWebClient::new(&addr, 10, OverflowPolicy::DropOldest);
+        data_web_client.set_encoding(WireEncoding::MessagePack);
+        data_web_client.add_candles(vec![candle("AAPL", 1, 1_000)]);
+
+        assert_eq!(data_web_client.start_client(1).unwrap(), vec!["AAPL".to_string()]);
+        assert_eq!(server.join().unwrap(), (Some("encoding=msgpack".to_string()), WireMessage::bar(&candle("AAPL", 1, 1_000), None)));
+    }
+
+    #[test]
+    fn pings_are_answered_and_a_close_reconnects() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = format!("ws://{}", listener.local_addr().unwrap());
+
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket = accept(stream).unwrap();
+
+            socket.send(Message::text("AAPL|")).unwrap();
+            socket.send(Message::Ping(b"alive".to_vec())).unwrap();
+
+            let pong = loop {
+                if let Message::Pong(payload) = socket.read().unwrap() {
+                    break payload;
+                }
+            };
+
+            socket.close(None).unwrap();
+            while socket.read().is_ok() {}
+
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket = accept(stream).unwrap();
+
+            socket.send(Message::text("AAPL|")).unwrap();
+
+            pong
+        });
+
+        let data_web_client = DataWebClient::new(&addr, 10, OverflowPolicy::DropOldest);
+
+        assert_eq!(data_web_client.start_client(1).unwrap(), vec!["AAPL".to_string()]);
+        assert_eq!(server.join().unwrap(), b"alive".to_vec());
+    }
+
+    #[test]
+    fn startup_reports_an_unavailable_data_store() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = format!("ws://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let data_web_client = DataWebClient::new(&addr, 10, OverflowPolicy::DropOldest);
+
+        assert!(matches!(data_web_client.start_client(1), Err(DataStoreError::Connect(_))));
+    }
+
+    #[test]
+    fn startup_expects_the_symbol_list() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = format!("ws://{}", listener.local_addr().unwrap());
+
+        thread::spawn(move || {
+            for _ in 0..2 {
+                let (stream, _) = listener.accept().unwrap();
+                let mut socket = accept(stream).unwrap();
+
+                socket.send(Message::Ping(Vec::new())).unwrap();
+                socket.send(Message::Binary(vec![1, 2, 3])).unwrap();
+                thread::sleep(Duration::from_millis(200));
+            }
+        });
+
+        let data_web_client = DataWebClient::new(&addr, 10, OverflowPolicy::DropOldest);
+
+        match data_web_client.start_client(1) {
+            Err(e @ DataStoreError::UnexpectedMessage(_)) => assert_eq!(e.to_string(), "Expected the symbol list, received a binary message"),
+            _ => panic!("Expected an unexpected message"),
+        };
+    }
+}
@@ -1,2 +1,8 @@
 pub mod data_web_client;
-pub mod trade_web_server;
\ No newline at end of file
+pub mod trade_web_server;
+pub mod trade_journal;
+pub mod candle_outbox;
+pub mod data_store_error;
+pub mod subscriptions;
+pub mod wire_format;
+pub mod query_server;
@@ -0,0 +1,256 @@
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    time::Duration,
+    net::{TcpStream, SocketAddr},
+};
+
+use crate::data_analysis::stock_analysis::StockAnalyserWeb;
+use crate::database_clients::trade_web_server::serve_connections;
+use crate::database_clients::wire_format::{WireBody, WireMessage};
+
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_HEADER_LINES: usize = 100;
+
+/*
+    Plain http api for what the process currently knows, e.g.
+        curl http://localhost:9011/snapshot
+        curl http://localhost:9011/snapshot/AAPL
+    Answers are "snapshot" or "error" messages of the wire format, one request per
+    connection.
+*/
+#[derive(Clone)]
+pub struct QueryServer {
+    ip_server: String,
+    stock_analysis_web: StockAnalyserWeb,
+}
+
+impl QueryServer {
+    pub fn new(ip_server: &str, stock_analysis_web: StockAnalyserWeb) -> Self {
+        QueryServer { ip_server: ip_server.to_string(), stock_analysis_web }
+    }
+
+    pub fn start_server(&self) -> io::Result<SocketAddr> {
+        let query_server = self.clone();
+
+        serve_connections(&self.ip_server, move |stream| query_server.serve_client(stream))
+    }
+
+    fn serve_client(&self, mut stream: TcpStream) {
+        let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+
+        let request_line = match read_request_line(&stream) {
+            Ok(v) => v,
+            Err(_) => return,
+        };
+
+        let (status, body) = self.respond(&request_line);
+        let message = WireMessage::new(body).to_json();
+
+        let response = format!(
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            message.len() + 1,
+            message,
+        );
+
+        if let Err(e) = stream.write_all(response.as_bytes()).and_then(|_| stream.write_all(b"\n")) {
+            println!("Error answering query {}", e);
+        }
+    }
+
+    /*
+        "GET /snapshot/AAPL HTTP/1.1"
+    */
+    fn respond(&self, request_line: &str) -> (&'static str, WireBody) {
+        let mut parts = request_line.split_whitespace();
+
+        let (method, target) = match (parts.next(), parts.next()) {
+            (Some(method), Some(target)) => (method, target),
+            _ => return ("400 Bad Request", WireBody::Error { request: request_line.to_string(), message: "Invalid request".to_string() }),
+        };
+
+        if method != "GET" {
+            return ("405 Method Not Allowed", WireBody::Error { request: request_line.to_string(), message: format!("Unsupported method: {}", method) });
+        }
+
+        let path = target.split('?').next().unwrap_or("").trim_end_matches('/');
+
+        let symbol = match path.strip_prefix("/snapshot") {
+            Some("") => None,
+            Some(v) if v.starts_with('/') => match percent_decode(&v[1..]) {
+                Some(v) => Some(v),
+                None => return ("400 Bad Request", WireBody::Error { request: request_line.to_string(), message: format!("Invalid symbol: {}", &v[1..]) }),
+            },
+            _ => return ("404 Not Found", WireBody::Error { request: request_line.to_string(), message: format!("Unknown path: {}", target) }),
+        };
+
+        let list_of_states = self.stock_analysis_web.symbol_states(symbol.as_deref());
+
+        match symbol {
+            Some(v) if list_of_states.is_empty() => ("404 Not Found", WireBody::Error { request: request_line.to_string(), message: format!("No trades for {}", v) }),
+            _ => ("200 OK", WireBody::Snapshot { symbols: list_of_states }),
+        }
+    }
+}
+
+/*
+    The headers are read but not needed
+*/
+fn read_request_line(stream: &TcpStream) -> io::Result<String> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    let mut line = String::new();
+
+    for _ in 0..MAX_HEADER_LINES {
+        line.clear();
+
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+    }
+
+    Ok(request_line.trim().to_string())
+}
+
+/*
+    BINANCE%3ABTCUSDT is BINANCE:BTCUSDT
+*/
+fn percent_decode(raw_value: &str) -> Option<String> {
+    let bytes = raw_value.as_bytes();
+    let mut decoded: Vec<u8> = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = raw_value.get(i + 1..i + 3)?;
+
+                decoded.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 3;
+            },
+            v => {
+                decoded.push(v);
+                i += 1;
+            },
+        };
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpStream};
+    use std::sync::Arc;
+
+    use crate::values_store::app_config::{BarPolicy, DedupRule, LateTradePolicy, OverflowPolicy, ProviderKind};
+    use crate::values_store::price_precision::PricePrecision;
+    use crate::values_store::candle_intervals::CandleIntervals;
+    use crate::database_clients::data_web_client::DataWebClient;
+    use crate::database_clients::trade_web_server::TradeWebServer;
+    use crate::database_clients::query_server::{QueryServer, percent_decode};
+    use crate::database_clients::wire_format::{WireBody, WireMessage, SymbolState};
+    use crate::data_analysis::stock_analysis::StockAnalyserWeb;
+    use crate::data_analysis::trade_consolidator::TradeConsolidator;
+    use crate::data_analysis::clock::ManualClock;
+    use crate::data_analysis::trade::Trade;
+
+    // 2024-09-06 15:27:00 UTC
+    const MINUTE: i64 = 1_725_636_420_000;
+
+
+    fn query(addr: SocketAddr, request: &str) -> (String, WireMessage) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "{}\r\nHost: localhost\r\n\r\n", request).unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+
+        (head.lines().next().unwrap().to_string(), serde_json::from_str(body).unwrap())
+    }
+
+    fn stock_analysis_web(clock: &ManualClock) -> StockAnalyserWeb {
+        let mut stock_analysis_web = StockAnalyserWeb::new(
+            DataWebClient::new("ws://127.0.0.1:9", 1_000, OverflowPolicy::DropOldest),
+            TradeWebServer::new("127.0.0.1:9"),
+            TradeConsolidator::new(DedupRule::FirstArrival, ProviderKind::Finnhub),
+            PricePrecision::new(2),
+            CandleIntervals::new(vec![60]),
+            BarPolicy { grace_ms: 500, late_trades: LateTradePolicy::Drop },
+        );
+        stock_analysis_web.set_clock(Arc::new(clock.clone()));
+
+        stock_analysis_web
+    }
+
+    fn snapshot(addr: SocketAddr, request: &str) -> Vec<SymbolState> {
+        match query(addr, request).1.body {
+            WireBody::Snapshot { symbols } => symbols,
+            body => panic!("Expected a snapshot, got {:?}", body),
+        }
+    }
+
+    #[test]
+    fn answers_with_the_state_of_the_symbols() {
+        let clock = ManualClock::new(MINUTE + 5_000);
+        let mut stock_analysis_web = stock_analysis_web(&clock);
+
+        let addr = QueryServer::new("127.0.0.1:0", stock_analysis_web.clone()).start_server().unwrap();
+
+        stock_analysis_web.add_trades(vec![Trade::fixture("AAPL", 10.5, MINUTE + 1_000).with_size(10), Trade::fixture("BINANCE:BTCUSDT", 60_000.0, MINUTE + 2_000).with_size(10)]);
+        clock.advance(1_500);
+
+        let (status, message) = query(addr, "GET /snapshot HTTP/1.1");
+        let list_of_states = match message.body {
+            WireBody::Snapshot { symbols } => symbols,
+            body => panic!("Expected a snapshot, got {:?}", body),
+        };
+
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(list_of_states.iter().map(|v| v.symbol.as_str()).collect::<Vec<_>>(), vec!["AAPL", "BINANCE:BTCUSDT"]);
+        assert_eq!(list_of_states[0].last_trade.as_ref().unwrap().price, "10.50");
+        assert_eq!(list_of_states[0].today.as_ref().unwrap().volume, 10);
+        assert_eq!(list_of_states[0].current_bars.len(), 2);
+        assert_eq!(list_of_states[0].ms_since_update, Some(1_500));
+
+        let (status, message) = query(addr, "GET /snapshot/BINANCE%3ABTCUSDT HTTP/1.1");
+
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert!(matches!(message.body, WireBody::Snapshot { symbols } if symbols.len() == 1 && symbols[0].symbol == "BINANCE:BTCUSDT"));
+
+        assert_eq!(query(addr, "GET /snapshot/MSFT HTTP/1.1").0, "HTTP/1.1 404 Not Found");
+        assert_eq!(query(addr, "GET /trades HTTP/1.1").0, "HTTP/1.1 404 Not Found");
+        assert_eq!(query(addr, "POST /snapshot HTTP/1.1").0, "HTTP/1.1 405 Method Not Allowed");
+    }
+
+    #[test]
+    fn today_is_the_day_in_new_york() {
+        // Saturday 2024-09-07 00:30:00 UTC, 20:30 on Friday in New York
+        let evening = 1_725_669_000_000;
+        let clock = ManualClock::new(evening + 5_000);
+        let mut stock_analysis_web = stock_analysis_web(&clock);
+
+        let addr = QueryServer::new("127.0.0.1:0", stock_analysis_web.clone()).start_server().unwrap();
+
+        stock_analysis_web.add_trades(vec![Trade::fixture("AAPL", 10.5, MINUTE).with_size(10), Trade::fixture("AAPL", 11.0, evening).with_size(5)]);
+
+        let list_of_states = snapshot(addr, "GET /snapshot/AAPL HTTP/1.1");
+        let today = list_of_states[0].today.as_ref().unwrap();
+
+        // Friday 2024-09-06 04:00:00 UTC, midnight in New York
+        assert_eq!(today.start, 1_725_595_200_000);
+        assert_eq!((today.open.as_str(), today.close.as_str(), today.volume), ("10.50", "11.00", 15));
+    }
+
+    #[test]
+    fn symbols_are_percent_decoded() {
+        assert_eq!(percent_decode("BINANCE%3aBTCUSDT"), Some("BINANCE:BTCUSDT".to_string()));
+        assert_eq!(percent_decode("AAPL"), Some("AAPL".to_string()));
+        assert_eq!(percent_decode("AAPL%3"), None);
+    }
+}
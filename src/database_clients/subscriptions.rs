@@ -0,0 +1,268 @@
+use crate::values_store::candle_intervals::parse_interval;
+use crate::database_clients::wire_format::WireBody;
+
+/*
+    Symbols a trade server client wants trades and bars of, as patterns where * stands for
+    any number of characters and ? for exactly one, e.g. "AAPL", "BINANCE:*" or "MSF?".
+    Bars are chosen as pattern@interval, e.g. "AAPL@1m", or by the pattern alone for
+    every interval.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Subscriptions {
+    patterns: Vec<String>,
+    candle_patterns: Vec<CandlePattern>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CandlePattern {
+    text: String,
+    pattern: String,
+    interval_seconds: Option<usize>,
+}
+
+/*
+    Requests of a client, one text message each:
+        subscribe;AAPL,BINANCE:*
+        unsubscribe;AAPL
+        subscriptions
+        subscribe-candles;AAPL@1m,BINANCE:*@5s,MSFT
+        unsubscribe-candles;MSFT
+        candle-subscriptions
+*/
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Subscribe(Vec<String>),
+    Unsubscribe(Vec<String>),
+    List,
+    SubscribeCandles(Vec<CandlePattern>),
+    UnsubscribeCandles(Vec<CandlePattern>),
+    ListCandles,
+}
+
+impl Subscriptions {
+    /*
+        Clients start with the trades of every symbol so the ones that never subscribe keep
+        working, and without bars
+    */
+    pub fn new() -> Self {
+        Subscriptions { patterns: vec!["*".to_string()], candle_patterns: Vec::new() }
+    }
+
+    pub fn matches(&self, symbol: &str) -> bool {
+        self.patterns.iter().any(|v| matches_pattern(v, symbol))
+    }
+
+    pub fn matches_candle(&self, symbol: &str, interval_seconds: usize) -> bool {
+        self.candle_patterns.iter().any(|v| v.interval_seconds.is_none_or(|i| i == interval_seconds) && matches_pattern(&v.pattern, symbol))
+    }
+
+    /*
+        Returns the ack for the client with the patterns of the request, or all patterns
+        for the listing requests
+    */
+    pub fn apply(&mut self, command: Command) -> WireBody {
+        let (request, patterns) = match command {
+            Command::Subscribe(patterns) => {
+                for pattern in patterns.iter() {
+                    if !self.patterns.contains(pattern) {
+                        self.patterns.push(pattern.clone());
+                    }
+                }
+
+                ("subscribe", patterns)
+            },
+            Command::Unsubscribe(patterns) => {
+                self.patterns.retain(|v| !patterns.contains(v));
+
+                ("unsubscribe", patterns)
+            },
+            Command::List => ("subscriptions", self.patterns.clone()),
+            Command::SubscribeCandles(candle_patterns) => {
+                let patterns = candle_texts(&candle_patterns);
+
+                for candle_pattern in candle_patterns.into_iter() {
+                    if !self.candle_patterns.iter().any(|v| v.same_as(&candle_pattern)) {
+                        self.candle_patterns.push(candle_pattern);
+                    }
+                }
+
+                ("subscribe-candles", patterns)
+            },
+            Command::UnsubscribeCandles(candle_patterns) => {
+                self.candle_patterns.retain(|v| !candle_patterns.iter().any(|c| c.same_as(v)));
+
+                ("unsubscribe-candles", candle_texts(&candle_patterns))
+            },
+            Command::ListCandles => ("candle-subscriptions", candle_texts(&self.candle_patterns)),
+        };
+
+        WireBody::Ack { request: request.to_string(), patterns }
+    }
+}
+
+impl CandlePattern {
+    /*
+        "AAPL@1m" or "AAPL" for every interval
+    */
+    pub fn parse(raw_value: &str) -> Result<Self, String> {
+        let (pattern, interval_seconds) = match raw_value.rsplit_once('@') {
+            Some((pattern, interval)) => (pattern, Some(parse_interval(interval)?)),
+            None => (raw_value, None),
+        };
+
+        if pattern.is_empty() {
+            return Err(format!("Invalid symbol pattern: {}", raw_value));
+        }
+
+        Ok(CandlePattern { text: raw_value.to_string(), pattern: pattern.to_string(), interval_seconds })
+    }
+
+    /*
+        "AAPL@1m" and "AAPL@60s" are the same subscription
+    */
+    fn same_as(&self, other: &CandlePattern) -> bool {
+        self.pattern == other.pattern && self.interval_seconds == other.interval_seconds
+    }
+}
+
+impl Command {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let (name, patterns) = match text.trim().split_once(';') {
+            Some((name, patterns)) => (name, parse_patterns(patterns)?),
+            None => (text.trim(), Vec::new()),
+        };
+
+        match (name.to_lowercase().as_str(), patterns.is_empty()) {
+            ("subscribe", false) => Ok(Command::Subscribe(patterns)),
+            ("unsubscribe", false) => Ok(Command::Unsubscribe(patterns)),
+            ("subscriptions", true) => Ok(Command::List),
+            ("subscribe-candles", false) => Ok(Command::SubscribeCandles(parse_candle_patterns(&patterns)?)),
+            ("unsubscribe-candles", false) => Ok(Command::UnsubscribeCandles(parse_candle_patterns(&patterns)?)),
+            ("candle-subscriptions", true) => Ok(Command::ListCandles),
+            ("subscribe", true) | ("unsubscribe", true) | ("subscribe-candles", true) | ("unsubscribe-candles", true) => {
+                Err("No symbols given".to_string())
+            },
+            _ => Err("Unknown request".to_string()),
+        }
+    }
+}
+
+fn parse_candle_patterns(patterns: &[String]) -> Result<Vec<CandlePattern>, String> {
+    patterns.iter().map(|v| CandlePattern::parse(v)).collect()
+}
+
+fn candle_texts(candle_patterns: &[CandlePattern]) -> Vec<String> {
+    candle_patterns.iter().map(|v| v.text.clone()).collect()
+}
+
+fn parse_patterns(raw_value: &str) -> Result<Vec<String>, String> {
+    let mut patterns: Vec<String> = Vec::new();
+
+    for pattern in raw_value.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+        if pattern.contains(char::is_whitespace) {
+            return Err(format!("Invalid symbol pattern: {}", pattern));
+        }
+
+        patterns.push(pattern.to_string());
+    }
+
+    Ok(patterns)
+}
+
+fn matches_pattern(pattern: &str, symbol: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let symbol: Vec<char> = symbol.chars().collect();
+
+    // matched[j]: the pattern so far matches the first j characters of the symbol
+    let mut matched: Vec<bool> = vec![false; symbol.len() + 1];
+    matched[0] = true;
+
+    for p in pattern.iter() {
+        let mut next: Vec<bool> = vec![false; symbol.len() + 1];
+
+        for j in 0..=symbol.len() {
+            next[j] = match p {
+                '*' => matched[j] || (j > 0 && next[j - 1]),
+                '?' => j > 0 && matched[j - 1],
+                c => j > 0 && matched[j - 1] && symbol[j - 1] == *c,
+            };
+        }
+
+        matched = next;
+    }
+
+    matched[symbol.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data_parsers::fixtures::ack;
+    use crate::database_clients::subscriptions::{Command, CandlePattern, Subscriptions, matches_pattern};
+
+    #[test]
+    fn patterns_match_symbols() {
+        assert!(matches_pattern("AAPL", "AAPL"));
+        assert!(!matches_pattern("AAPL", "AAPL2"));
+        assert!(matches_pattern("BINANCE:*", "BINANCE:BTCUSDT"));
+        assert!(!matches_pattern("BINANCE:*", "OANDA:EUR_USD"));
+        assert!(matches_pattern("MSF?", "MSFT"));
+        assert!(!matches_pattern("MSF?", "MSF"));
+        assert!(matches_pattern("*USD*", "OANDA:EUR_USD"));
+        assert!(matches_pattern("*", ""));
+    }
+
+    #[test]
+    fn parses_requests() {
+        assert_eq!(Command::parse("subscribe;AAPL, BINANCE:*").unwrap(), Command::Subscribe(vec!["AAPL".to_string(), "BINANCE:*".to_string()]));
+        assert_eq!(Command::parse("UNSUBSCRIBE;*").unwrap(), Command::Unsubscribe(vec!["*".to_string()]));
+        assert_eq!(Command::parse("subscriptions").unwrap(), Command::List);
+
+        assert!(Command::parse("subscribe;").is_err());
+        assert!(Command::parse("subscribe").is_err());
+        assert!(Command::parse("history;AAPL").is_err());
+
+        assert_eq!(Command::parse("subscribe-candles;AAPL@1m,MSFT").unwrap(), Command::SubscribeCandles(vec![
+            CandlePattern { text: "AAPL@1m".to_string(), pattern: "AAPL".to_string(), interval_seconds: Some(60) },
+            CandlePattern { text: "MSFT".to_string(), pattern: "MSFT".to_string(), interval_seconds: None },
+        ]));
+        assert_eq!(Command::parse("candle-subscriptions").unwrap(), Command::ListCandles);
+
+        assert!(Command::parse("subscribe-candles;AAPL@7m").is_err());
+        assert!(Command::parse("subscribe-candles;@1m").is_err());
+    }
+
+    #[test]
+    fn candles_are_chosen_by_symbol_and_interval() {
+        let mut subscriptions = Subscriptions::new();
+
+        assert!(!subscriptions.matches_candle("AAPL", 60));
+
+        let reply = subscriptions.apply(Command::parse("subscribe-candles;AAPL@1m,BINANCE:*").unwrap());
+
+        assert_eq!(reply, ack("subscribe-candles", &["AAPL@1m", "BINANCE:*"]));
+        assert!(subscriptions.matches_candle("AAPL", 60));
+        assert!(!subscriptions.matches_candle("AAPL", 1));
+        assert!(subscriptions.matches_candle("BINANCE:BTCUSDT", 1));
+
+        subscriptions.apply(Command::parse("unsubscribe-candles;BINANCE:*").unwrap());
+
+        assert!(!subscriptions.matches_candle("BINANCE:BTCUSDT", 1));
+        assert_eq!(subscriptions.apply(Command::ListCandles), ack("candle-subscriptions", &["AAPL@1m"]));
+    }
+
+    #[test]
+    fn requests_change_the_subscriptions() {
+        let mut subscriptions = Subscriptions::new();
+
+        assert!(subscriptions.matches("TSM"));
+        assert_eq!(subscriptions.apply(Command::Unsubscribe(vec!["*".to_string()])), ack("unsubscribe", &["*"]));
+        assert!(!subscriptions.matches("TSM"));
+
+        assert_eq!(subscriptions.apply(Command::Subscribe(vec!["AAPL".to_string(), "BINANCE:*".to_string()])), ack("subscribe", &["AAPL", "BINANCE:*"]));
+        assert!(subscriptions.matches("AAPL"));
+        assert!(subscriptions.matches("BINANCE:ETHUSDT"));
+        assert!(!subscriptions.matches("MSFT"));
+
+        assert_eq!(subscriptions.apply(Command::List), ack("subscriptions", &["AAPL", "BINANCE:*"]));
+    }
+}
@@ -0,0 +1,449 @@
+use std::{
+    thread,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    collections::HashMap,
+    sync::mpsc::{self, Sender, RecvTimeoutError},
+    time::{Duration, Instant},
+};
+
+use chrono::{Duration as ChronoDuration, NaiveDate};
+
+use crate::data_analysis::trade::Trade;
+use crate::data_analysis::price::Price;
+use crate::values_store::app_config::ProviderKind;
+
+const MINUTE_MS: i64 = 60_000;
+
+/*
+    Append-only tape of every validated trade. Trades are written to
+    <dir>/<group>/<YYYY-MM-DD>.log, one line per trade:
+        timestamp_ms \t symbol \t price_units \t size \t source \t exchange \t trade_id \t conditions
+    The day is the UTC day of the exchange timestamp, the group is the vendor prefix of the
+    symbol ("BINANCE:BTCUSDT" -> "binance") or "equities" for plain tickers.
+
+    Every segment has an <YYYY-MM-DD>.idx next to it with a "minute_ms offset" line whenever a
+    trade of a later minute than all before is written, so a time range can be read without
+    scanning the whole day. A line cut off by a crash is removed when the segment is reopened
+    and the index is rebuilt from the log.
+*/
+pub struct TradeJournal {
+    dir: PathBuf,
+    segments: HashMap<(String, NaiveDate), Segment>,
+}
+
+struct Segment {
+    log: File,
+    index: File,
+    length: u64,
+    last_minute: i64,
+}
+
+impl TradeJournal {
+    pub fn new(dir: &str) -> Self {
+        TradeJournal { dir: PathBuf::from(dir), segments: HashMap::new() }
+    }
+
+    pub fn append(&mut self, trade: &Trade) -> io::Result<()> {
+        let timestamp = trade.timestamp_millis();
+        let day = trade.timestamp.date_naive();
+        let key = (symbol_group(&trade.symbol), day);
+
+        if !self.segments.contains_key(&key) {
+            let segment = Segment::open(&self.dir.join(&key.0), day)?;
+
+            // keep yesterday open for trades that arrive late
+            self.segments.retain(|(_, v), _| *v >= day - ChronoDuration::days(1));
+            self.segments.insert(key.clone(), segment);
+        }
+
+        self.segments.get_mut(&key).unwrap().append(timestamp, &trade_to_line(trade))
+    }
+
+    pub fn sync(&mut self) -> io::Result<()> {
+        for segment in self.segments.values_mut() {
+            segment.log.sync_data()?;
+            segment.index.sync_data()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Segment {
+    fn open(dir: &Path, day: NaiveDate) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+
+        let (log_path, index_path) = segment_paths(dir, day);
+        let mut log = OpenOptions::new().read(true).append(true).create(true).open(&log_path)?;
+
+        let mut content: Vec<u8> = Vec::new();
+        log.read_to_end(&mut content)?;
+
+        let length = match content.iter().rposition(|v| *v == b'\n') {
+            Some(v) => v as u64 + 1,
+            None => 0,
+        };
+
+        if length < content.len() as u64 {
+            println!("Removing incomplete journal line at the end of {}", log_path.display());
+            log.set_len(length)?;
+        }
+
+        let mut index = File::create(&index_path)?;
+        let mut last_minute = i64::MIN;
+        let mut offset: u64 = 0;
+
+        for line in content[..length as usize].split(|v| *v == b'\n').filter(|v| !v.is_empty()) {
+            if let Some(timestamp) = line_timestamp(line) {
+                let minute = timestamp - timestamp.rem_euclid(MINUTE_MS);
+
+                if minute > last_minute {
+                    writeln!(index, "{} {}", minute, offset)?;
+                    last_minute = minute;
+                }
+            }
+
+            offset += line.len() as u64 + 1;
+        }
+
+        Ok(Segment { log, index, length, last_minute })
+    }
+
+    fn append(&mut self, timestamp: i64, line: &str) -> io::Result<()> {
+        let minute = timestamp - timestamp.rem_euclid(MINUTE_MS);
+
+        self.log.write_all(format!("{}\n", line).as_bytes())?;
+
+        if minute > self.last_minute {
+            writeln!(self.index, "{} {}", minute, self.length)?;
+            self.last_minute = minute;
+        }
+
+        self.length += line.len() as u64 + 1;
+
+        Ok(())
+    }
+}
+
+/*
+    Writes the trades on its own thread so the analysis never waits for the disk.
+    The segments are synced at least once per second.
+*/
+pub fn start_journal(mut trade_journal: TradeJournal) -> Sender<Trade> {
+    let (sender, receiver) = mpsc::channel::<Trade>();
+
+    thread::spawn(move || {
+        let mut last_sync = Instant::now();
+
+        loop {
+            match receiver.recv_timeout(Duration::from_millis(1000)) {
+                Ok(trade) => {
+                    if let Err(e) = trade_journal.append(&trade) {
+                        println!("Error writing trade to the journal: {}", e);
+                    }
+                },
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => {
+                    let _ = trade_journal.sync();
+                    return;
+                },
+            };
+
+            if last_sync.elapsed() >= Duration::from_millis(1000) {
+                if let Err(e) = trade_journal.sync() {
+                    println!("Error syncing the journal: {}", e);
+                }
+
+                last_sync = Instant::now();
+            }
+        }
+    });
+
+    sender
+}
+
+/*
+    Trades of a group with from_ms <= timestamp < to_ms, in the order they were written
+*/
+pub fn read_range(dir: &str, group: &str, from_ms: i64, to_ms: i64) -> io::Result<Vec<Trade>> {
+    let mut list_of_trades: Vec<Trade> = Vec::new();
+
+    let (first_day, last_day) = match (Trade::timestamp_from_millis(from_ms), Trade::timestamp_from_millis(to_ms - 1)) {
+        (Some(from), Some(to)) if from_ms < to_ms => (from.date_naive(), to.date_naive()),
+        _ => return Ok(list_of_trades),
+    };
+
+    let group_dir = Path::new(dir).join(group);
+    let from_minute = from_ms - from_ms.rem_euclid(MINUTE_MS);
+
+    for day in first_day.iter_days().take_while(|v| *v <= last_day) {
+        let (log_path, index_path) = segment_paths(&group_dir, day);
+
+        if !log_path.exists() {
+            continue;
+        }
+
+        let offset = match start_offset(&index_path, from_minute)? {
+            Some(v) => v,
+            None => continue,
+        };
+
+        let mut log = File::open(&log_path)?;
+        log.seek(SeekFrom::Start(offset))?;
+
+        for line in BufReader::new(log).split(b'\n') {
+            let trade = match trade_from_line(&String::from_utf8_lossy(&line?)) {
+                Some(v) => v,
+                None => continue,
+            };
+
+            if trade.timestamp_millis() >= from_ms && trade.timestamp_millis() < to_ms {
+                list_of_trades.push(trade);
+            }
+        }
+    }
+
+    Ok(list_of_trades)
+}
+
+/*
+    Groups that have at least one segment in the journal
+*/
+pub fn list_groups(dir: &str) -> io::Result<Vec<String>> {
+    let mut list_of_groups: Vec<String> = Vec::new();
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+
+        if entry.file_type()?.is_dir() {
+            list_of_groups.push(entry.file_name().to_string_lossy().to_string());
+        }
+    }
+
+    list_of_groups.sort();
+
+    Ok(list_of_groups)
+}
+
+/*
+    Start of the first and end of the last day with a segment in any group
+*/
+pub fn journal_bounds(dir: &str) -> io::Result<Option<(i64, i64)>> {
+    let mut list_of_days: Vec<NaiveDate> = Vec::new();
+
+    for group in list_groups(dir)?.iter() {
+        for entry in fs::read_dir(Path::new(dir).join(group))? {
+            let file_name = entry?.file_name().to_string_lossy().to_string();
+
+            if let Some(Ok(day)) = file_name.strip_suffix(".log").map(|v| NaiveDate::parse_from_str(v, "%Y-%m-%d")) {
+                list_of_days.push(day);
+            }
+        }
+    }
+
+    let (first_day, last_day) = match (list_of_days.iter().min(), list_of_days.iter().max()) {
+        (Some(first), Some(last)) => (*first, *last + ChronoDuration::days(1)),
+        _ => return Ok(None),
+    };
+
+    Ok(Some((
+        first_day.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_millis(),
+        last_day.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_millis(),
+    )))
+}
+
+pub fn symbol_group(symbol: &str) -> String {
+    match symbol.split_once(':') {
+        Some((prefix, _)) if !prefix.is_empty() => prefix
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+            .collect(),
+        _ => "equities".to_string(),
+    }
+}
+
+/*
+    Offset of the first trade of a minute >= from_minute, nothing before it can be in range
+*/
+fn start_offset(index_path: &Path, from_minute: i64) -> io::Result<Option<u64>> {
+    let index = match File::open(index_path) {
+        Ok(v) => v,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Some(0)),
+        Err(e) => return Err(e),
+    };
+
+    for line in BufReader::new(index).lines() {
+        let line = line?;
+
+        let (minute, offset) = match line.split_once(' ') {
+            Some((m, o)) => (m.parse::<i64>(), o.parse::<u64>()),
+            None => continue,
+        };
+
+        if let (Ok(minute), Ok(offset)) = (minute, offset) {
+            if minute >= from_minute {
+                return Ok(Some(offset));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+fn segment_paths(dir: &Path, day: NaiveDate) -> (PathBuf, PathBuf) {
+    let name = day.format("%Y-%m-%d").to_string();
+
+    (dir.join(format!("{}.log", name)), dir.join(format!("{}.idx", name)))
+}
+
+fn line_timestamp(line: &[u8]) -> Option<i64> {
+    let end = line.iter().position(|v| *v == b'\t')?;
+
+    std::str::from_utf8(&line[..end]).ok()?.parse::<i64>().ok()
+}
+
+fn clean_field(value: &str) -> String {
+    value.replace(['\t', '\n', '\r'], " ")
+}
+
+pub fn trade_to_line(trade: &Trade) -> String {
+    format!("{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+        trade.timestamp_millis(),
+        clean_field(&trade.symbol),
+        trade.price.units(),
+        trade.size,
+        trade.source,
+        clean_field(trade.exchange.as_deref().unwrap_or("")),
+        clean_field(trade.trade_id.as_deref().unwrap_or("")),
+        trade.conditions.iter().map(|v| clean_field(v).replace(',', " ")).collect::<Vec<String>>().join(","),
+    )
+}
+
+pub fn trade_from_line(line: &str) -> Option<Trade> {
+    let fields: Vec<&str> = line.split('\t').collect();
+
+    if fields.len() != 8 {
+        return None;
+    }
+
+    let optional = |v: &str| if v.is_empty() { None } else { Some(v.to_string()) };
+
+    Some(Trade {
+        timestamp: Trade::timestamp_from_millis(fields[0].parse::<i64>().ok()?)?,
+        symbol: fields[1].to_string(),
+        price: Price::from_units(fields[2].parse::<i64>().ok()?),
+        size: fields[3].parse::<i64>().ok()?,
+        source: ProviderKind::parse(fields[4]).ok()?,
+        exchange: optional(fields[5]),
+        trade_id: optional(fields[6]),
+        conditions: match fields[7] {
+            "" => Vec::new(),
+            v => v.split(',').map(|s| s.to_string()).collect(),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    use crate::values_store::app_config::ProviderKind;
+    use crate::data_analysis::trade::Trade;
+    use crate::database_clients::trade_journal::{TradeJournal, read_range, list_groups, journal_bounds, symbol_group, trade_to_line, trade_from_line};
+
+    // 2024-09-06 15:27:00 UTC
+    const MINUTE: i64 = 1_725_636_420_000;
+    // 2024-09-07 00:00:00 UTC
+    const NEXT_DAY: i64 = 1_725_667_200_000;
+
+    fn journal_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("stockwatch-journal-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        dir
+    }
+
+
+    #[test]
+    fn lines_round_trip() {
+        let mut original = Trade {
+            exchange: Some("V".to_string()),
+            conditions: vec!["@".to_string(), "I".to_string()],
+            trade_id: Some("55397666350414".to_string()),
+            ..Trade::fixture("BINANCE:BTCUSDT", 56912.01, MINUTE).with_source(ProviderKind::Alpaca)
+        };
+
+        assert_eq!(trade_from_line(&trade_to_line(&original)), Some(original.clone()));
+
+        original.exchange = None;
+        original.trade_id = None;
+        original.conditions = Vec::new();
+
+        assert_eq!(trade_from_line(&trade_to_line(&original)), Some(original));
+        assert_eq!(trade_from_line("1725636420000\tAAPL\t100"), None);
+
+        assert_eq!(symbol_group("BINANCE:BTCUSDT"), "binance");
+        assert_eq!(symbol_group("AAPL"), "equities");
+    }
+
+    #[test]
+    fn reads_time_ranges_across_days() {
+        let dir = journal_dir("range");
+        let mut trade_journal = TradeJournal::new(dir.to_str().unwrap());
+
+        for i in 0..5 {
+            trade_journal.append(&Trade::fixture("AAPL", 222.25, MINUTE + i * 60_000)).unwrap();
+        }
+
+        trade_journal.append(&Trade::fixture("AAPL", 222.5, MINUTE + 30_000)).unwrap();
+        trade_journal.append(&Trade::fixture("BINANCE:BTCUSDT", 56912.01, NEXT_DAY + 1_000)).unwrap();
+        trade_journal.append(&Trade::fixture("AAPL", 223.0, NEXT_DAY + 2_000)).unwrap();
+        trade_journal.sync().unwrap();
+
+        let list_of_trades = read_range(dir.to_str().unwrap(), "equities", MINUTE + 60_000, MINUTE + 180_000).unwrap();
+
+        assert_eq!(list_of_trades.iter().map(|v| v.timestamp_millis()).collect::<Vec<i64>>(), vec![MINUTE + 60_000, MINUTE + 120_000]);
+
+        let list_of_trades = read_range(dir.to_str().unwrap(), "equities", MINUTE + 10_000, NEXT_DAY + 10_000).unwrap();
+
+        assert_eq!(list_of_trades.len(), 6);
+        assert_eq!(list_of_trades[0].price.to_string(), "222.25");
+        assert_eq!(list_of_trades[4].price.to_string(), "222.5");
+        assert_eq!(list_of_trades[5].price.to_string(), "223");
+
+        assert_eq!(list_groups(dir.to_str().unwrap()).unwrap(), vec!["binance".to_string(), "equities".to_string()]);
+        assert_eq!(journal_bounds(dir.to_str().unwrap()).unwrap(), Some((NEXT_DAY - 86_400_000, NEXT_DAY + 86_400_000)));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn recovers_from_a_torn_write() {
+        let dir = journal_dir("torn");
+        let mut trade_journal = TradeJournal::new(dir.to_str().unwrap());
+
+        trade_journal.append(&Trade::fixture("AAPL", 222.25, MINUTE)).unwrap();
+        drop(trade_journal);
+
+        let log_path = dir.join("equities").join("2024-09-06.log");
+        let mut log = OpenOptions::new().append(true).open(&log_path).unwrap();
+        log.write_all(b"1725636480000\tAAPL\t2222").unwrap();
+        let _ = fs::remove_file(dir.join("equities").join("2024-09-06.idx"));
+
+        let mut trade_journal = TradeJournal::new(dir.to_str().unwrap());
+        trade_journal.append(&Trade::fixture("AAPL", 222.75, MINUTE + 120_000)).unwrap();
+        trade_journal.sync().unwrap();
+
+        let list_of_trades = read_range(dir.to_str().unwrap(), "equities", MINUTE, MINUTE + 180_000).unwrap();
+
+        assert_eq!(list_of_trades.len(), 2);
+        assert_eq!(list_of_trades[1].price.to_string(), "222.75");
+        assert!(fs::read_to_string(&log_path).unwrap().lines().all(|v| trade_from_line(v).is_some()));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
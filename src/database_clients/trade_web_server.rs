@@ -1,78 +1,509 @@
 use std::{
     thread,
-    sync::{Arc, RwLock},
+    io::{self, ErrorKind},
+    sync::{Arc, Mutex, Condvar, OnceLock, atomic::{AtomicUsize, Ordering}},
     time::Duration,
-    collections::VecDeque,
-    net::TcpListener,
+    collections::{HashMap, VecDeque},
+    net::{TcpListener, TcpStream, SocketAddr},
 };
 
-use tungstenite::{accept, Message};
+use tungstenite::{
+    accept_hdr, Error, Message, WebSocket,
+    handshake::server::{Callback, ErrorResponse, Request, Response},
+};
+
+use crate::values_store::app_config::WireEncoding;
+use crate::data_analysis::trade::Trade;
+use crate::data_analysis::feed_supervisor::FailoverEvent;
+use crate::database_clients::data_web_client::DataTradeModel;
+use crate::database_clients::subscriptions::{Command, Subscriptions};
+use crate::database_clients::wire_format::{WireBody, WireMessage, encoding_from_query};
+
+const BUFFER_SIZE: usize = 10_000;
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/*
+    The last BUFFER_SIZE messages with consecutive sequence numbers. Every client reads
+    them with its own cursor, so clients don't take messages away from each other.
+*/
+struct Broadcast {
+    state: Mutex<BroadcastState>,
+    new_message: Condvar,
+}
+
+/*
+    Messages without a symbol go to every client, the ones with an interval are bars.
+    Each encoding is produced once, by the first client that needs it.
+*/
+struct BroadcastMessage {
+    symbol: Option<String>,
+    interval_seconds: Option<usize>,
+    message: WireMessage,
+    json: OnceLock<String>,
+    msgpack: OnceLock<Vec<u8>>,
+}
+
+impl BroadcastMessage {
+    fn new(symbol: Option<String>, interval_seconds: Option<usize>, message: WireMessage) -> Self {
+        BroadcastMessage { symbol, interval_seconds, message, json: OnceLock::new(), msgpack: OnceLock::new() }
+    }
+
+    fn encode(&self, encoding: WireEncoding) -> Message {
+        match encoding {
+            WireEncoding::Json => Message::text(self.json.get_or_init(|| self.message.to_json()).as_str()),
+            WireEncoding::MessagePack => Message::binary(self.msgpack.get_or_init(|| self.message.to_msgpack()).clone()),
+        }
+    }
+}
+
+struct BroadcastState {
+    first_sequence: u64,
+    messages: VecDeque<Arc<BroadcastMessage>>,
+    latest_bars: HashMap<(String, usize), (u64, Arc<BroadcastMessage>)>,
+}
+
+impl Broadcast {
+    fn push(&self, message: BroadcastMessage) {
+        let mut state = self.state.lock().unwrap();
+        let message = Arc::new(message);
+
+        if let (Some(symbol), Some(interval_seconds)) = (&message.symbol, message.interval_seconds) {
+            let sequence = state.first_sequence + state.messages.len() as u64;
+
+            state.latest_bars.insert((symbol.clone(), interval_seconds), (sequence, message.clone()));
+        }
 
-use crate::data_analysis::finnhub_data_row::FinnhubDataRow;
+        state.messages.push_back(message);
+
+        if state.messages.len() > BUFFER_SIZE {
+            state.messages.pop_front();
+            state.first_sequence += 1;
+        }
+
+        self.new_message.notify_all();
+    }
+
+    fn next_sequence(&self) -> u64 {
+        let state = self.state.lock().unwrap();
+
+        state.first_sequence + state.messages.len() as u64
+    }
+
+    /*
+        Waits up to timeout for messages from cursor on. Returns them with the cursor after
+        them and the number of messages that were already gone from the buffer.
+    */
+    fn read_from(&self, cursor: u64, timeout: Duration) -> (Vec<Arc<BroadcastMessage>>, u64, u64) {
+        let mut state = self.state.lock().unwrap();
+
+        if cursor >= state.first_sequence + state.messages.len() as u64 {
+            state = self.new_message.wait_timeout(state, timeout).unwrap().0;
+        }
+
+        let missed = state.first_sequence.saturating_sub(cursor);
+        let start = cursor.max(state.first_sequence);
+        let list_of_messages: Vec<Arc<BroadcastMessage>> = state.messages.iter().skip((start - state.first_sequence) as usize).cloned().collect();
+        let next_cursor = start + list_of_messages.len() as u64;
+
+        (list_of_messages, next_cursor, missed)
+    }
+
+    /*
+        The latest bar of every symbol and interval the client subscribed to. Bars the
+        cursor did not pass yet are left out, the client gets them with the stream.
+    */
+    fn snapshot(&self, cursor: u64, subscriptions: &Subscriptions) -> Vec<Arc<BroadcastMessage>> {
+        let state = self.state.lock().unwrap();
+
+        let mut list_of_bars: Vec<(u64, Arc<BroadcastMessage>)> = state.latest_bars.iter()
+            .filter(|((symbol, interval_seconds), (sequence, _))| *sequence < cursor && subscriptions.matches_candle(symbol, *interval_seconds))
+            .map(|(_, v)| v.clone())
+            .collect();
+
+        list_of_bars.sort_by_key(|v| v.0);
+        list_of_bars.into_iter().map(|v| v.1).collect()
+    }
+}
 
+/*
+    Streams the trades, bars and failover events to every connected client in the wire
+    format, see WireMessage, as text or as binary messages if the client asked for msgpack.
+    A client only gets what was added after it connected and only the trades and bars it
+    subscribed to, see Subscriptions. Subscribing to bars first sends the latest completed
+    bar of every matching symbol and interval.
+    A client that fell behind the buffer continues with the oldest buffered message after
+    a "lagged" notice, one that does not read at all is disconnected once a write timed out.
+*/
+#[derive(Clone)]
 pub struct TradeWebServer {
     ip_server: String,
-    update_queue: Arc<RwLock<VecDeque<String>>>,
+    broadcast: Arc<Broadcast>,
+    num_of_clients: Arc<AtomicUsize>,
 }
 
 impl TradeWebServer {
     pub fn new(ip_server: &str) -> Self {
-        TradeWebServer { 
-            ip_server: ip_server.to_string(), 
-            update_queue: Arc::new(RwLock::new(VecDeque::new())),
+        TradeWebServer {
+            ip_server: ip_server.to_string(),
+            broadcast: Arc::new(Broadcast {
+                state: Mutex::new(BroadcastState { first_sequence: 0, messages: VecDeque::new(), latest_bars: HashMap::new() }),
+                new_message: Condvar::new(),
+            }),
+            num_of_clients: Arc::new(AtomicUsize::new(0)),
         }
     }
 
-    pub fn add_trade(&mut self, trade:FinnhubDataRow) {
-        let n = self.update_queue.read().unwrap().len();
+    pub fn add_trade(&mut self, trade: Trade, price_decimals: u32) {
+        self.broadcast.push(BroadcastMessage::new(Some(trade.symbol.clone()), None, WireMessage::trade(&trade, price_decimals)));
+    }
 
-        if n > 10000 {
-            let _ = self.update_queue.write().unwrap().pop_front();
+    pub fn add_candles(&mut self, list_of_trades: &[DataTradeModel]) {
+        for database_model in list_of_trades.iter() {
+            self.broadcast.push(BroadcastMessage::new(
+                Some(database_model.stock_name.clone()),
+                Some(database_model.stock_interval),
+                WireMessage::bar(database_model, None),
+            ));
         }
+    }
+
+    pub fn add_failover(&mut self, event: &FailoverEvent) {
+        self.broadcast.push(BroadcastMessage::new(None, None, WireMessage::failover(event)));
+    }
+
+    pub fn num_of_clients(&self) -> usize {
+        self.num_of_clients.load(Ordering::Relaxed)
+    }
+
+    pub fn start_server(&self) -> io::Result<SocketAddr> {
+        let trade_web_server = self.clone();
+
+        serve_connections(&self.ip_server, move |stream| trade_web_server.serve_client(stream))
+    }
+
+    /*
+        The client picks the encoding with the query of its address, e.g.
+        ws://localhost:9010/?encoding=msgpack. One with an unknown encoding gets an error
+        in json and is disconnected.
+    */
+    fn serve_client(&self, stream: TcpStream) {
+        let mut query: Option<String> = None;
+
+        let mut websocket = match accept_hdr(stream, QueryCallback { query: &mut query }) {
+            Ok(v) => v,
+            Err(_) => return,
+        };
+
+        let encoding = match encoding_from_query(query.as_deref()) {
+            Ok(v) => v,
+            Err(e) => {
+                let _ = websocket.send(WireMessage::error(query.as_deref().unwrap_or(""), e).encode(WireEncoding::Json));
+                let _ = websocket.close(None);
+                let _ = websocket.flush();
+
+                return;
+            },
+        };
+
+        // reads only poll for control messages between the batches of messages
+        let _ = websocket.get_ref().set_read_timeout(Some(Duration::from_millis(1)));
+        let _ = websocket.get_ref().set_write_timeout(Some(WRITE_TIMEOUT));
+
+        let mut cursor = self.broadcast.next_sequence();
+        let mut subscriptions = Subscriptions::new();
+        self.num_of_clients.fetch_add(1, Ordering::Relaxed);
+
+        println!("Trade server client connected, {} connected", self.num_of_clients());
+
+        loop {
+            let (list_of_messages, next_cursor, missed) = self.broadcast.read_from(cursor, Duration::from_millis(50));
+            cursor = next_cursor;
+
+            if missed > 0 && websocket.write(WireMessage::new(WireBody::Lagged { missed }).encode(encoding)).is_err() {
+                break;
+            }
+
+            if !send_messages(&mut websocket, &list_of_messages, &subscriptions, encoding) {
+                break;
+            }
 
-        self.update_queue.write().unwrap().push_back(trade.to_string());
-    }
-
-    pub fn start_server(&self) {
-        let server = TcpListener::bind(self.ip_server.clone()).unwrap();
-        let update_queue_clone = self.update_queue.clone();
-
-        thread::spawn(move || {
-            for stream in server.incoming() {
-                let stream = match stream {
-                    Ok(v) => v,
-                    Err(_) => continue,
-                };
-
-                let mut websocket = match accept(stream) {
-                    Ok(v) => v,
-                    Err(_) => continue,
-                };
-
-                loop {
-                    let update = update_queue_clone.write().unwrap().pop_front();
-            
-                    let update = match update {
-                        Some(v) => v,
-                        None => {
-                            thread::sleep(Duration::from_millis(1));
-            
-                            continue;
-                        },
+            match websocket.read() {
+                Ok(Message::Text(text)) => {
+                    let (reply, is_candle_subscription) = match Command::parse(&text) {
+                        Ok(command @ Command::SubscribeCandles(_)) => (WireMessage::new(subscriptions.apply(command)), true),
+                        Ok(command) => (WireMessage::new(subscriptions.apply(command)), false),
+                        Err(e) => (WireMessage::error(text.as_str(), e), false),
                     };
-            
-                    match websocket.send(Message::text(&update)){
-                        Ok(v) => v,
-                        Err(e) => {
-                            println!("Error sending Message {}", e);
-            
-                            update_queue_clone.write().unwrap().push_front(update);
-            
-                            break;
-                        },
+
+                    if websocket.write(reply.encode(encoding)).is_err() {
+                        break;
+                    }
+
+                    let list_of_bars = match is_candle_subscription {
+                        true => self.broadcast.snapshot(cursor, &subscriptions),
+                        false => Vec::new(),
                     };
-                }
+
+                    if !send_messages(&mut websocket, &list_of_bars, &subscriptions, encoding) {
+                        break;
+                    }
+                },
+                Ok(Message::Close(_)) => break,
+                Ok(_) => (),
+                Err(Error::Io(ref error)) if error.kind() == ErrorKind::WouldBlock || error.kind() == ErrorKind::TimedOut => (),
+                Err(_) => break,
+            };
+        }
+
+        self.num_of_clients.fetch_sub(1, Ordering::Relaxed);
+
+        println!("Trade server client disconnected, {} connected", self.num_of_clients());
+    }
+}
+
+/*
+    Returns the address the server listens on, every connection is served on its own thread
+*/
+pub fn serve_connections<F: Fn(TcpStream) + Clone + Send + 'static>(ip_server: &str, serve_client: F) -> io::Result<SocketAddr> {
+    let server = TcpListener::bind(ip_server)?;
+    let local_addr = server.local_addr()?;
+
+    thread::spawn(move || {
+        for stream in server.incoming() {
+            let stream = match stream {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+
+            let serve_client = serve_client.clone();
+
+            thread::spawn(move || {
+                serve_client(stream);
+            });
+        }
+    });
+
+    Ok(local_addr)
+}
+
+/*
+    Keeps the query of the address a client connected to
+*/
+pub struct QueryCallback<'a> {
+    pub query: &'a mut Option<String>,
+}
+
+impl Callback for QueryCallback<'_> {
+    fn on_request(self, request: &Request, response: Response) -> Result<Response, ErrorResponse> {
+        *self.query = request.uri().query().map(|v| v.to_string());
+
+        Ok(response)
+    }
+}
+
+/*
+    Returns false once the client is gone
+*/
+fn send_messages(websocket: &mut WebSocket<TcpStream>, list_of_messages: &[Arc<BroadcastMessage>], subscriptions: &Subscriptions, encoding: WireEncoding) -> bool {
+    for message in list_of_messages.iter() {
+        let is_subscribed = match (&message.symbol, message.interval_seconds) {
+            (Some(symbol), Some(interval_seconds)) => subscriptions.matches_candle(symbol, interval_seconds),
+            (Some(symbol), None) => subscriptions.matches(symbol),
+            (None, _) => true,
+        };
+
+        if !is_subscribed {
+            continue;
+        }
+
+        if let Err(e) = websocket.write(message.encode(encoding)) {
+            println!("Error sending Message {}", e);
+            return false;
+        }
+    }
+
+    websocket.flush().is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::net::TcpStream;
+    use std::time::Duration;
+
+    use tungstenite::{connect, Message, WebSocket, stream::MaybeTlsStream};
+
+    use crate::values_store::app_config::{ProviderKind, WireEncoding};
+    use crate::data_analysis::price::Price;
+    use crate::data_analysis::trade::Trade;
+    use crate::data_analysis::feed_supervisor::FailoverEvent;
+    use crate::data_parsers::fixtures::ack;
+    use crate::database_clients::data_web_client::DataTradeModel;
+    use crate::database_clients::trade_web_server::{TradeWebServer, BroadcastMessage, BUFFER_SIZE};
+    use crate::database_clients::wire_format::{WireBody, WireMessage};
+
+    // 2024-09-06 15:27:00 UTC
+    const MINUTE: i64 = 1_725_636_420_000;
+
+    fn read_message(client: &mut WebSocket<MaybeTlsStream<TcpStream>>) -> WireMessage {
+        match client.read().unwrap() {
+            Message::Text(text) => serde_json::from_str(&text).unwrap(),
+            Message::Binary(payload) => rmp_serde::from_slice(&payload).unwrap(),
+            message => panic!("Expected a wire message, got {:?}", message),
+        }
+    }
+
+    fn wait_for_clients(trade_web_server: &TradeWebServer, num_of_clients: usize) {
+        for _ in 0..100 {
+            if trade_web_server.num_of_clients() == num_of_clients {
+                return;
             }
-        });
+
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        panic!("Expected {} clients", num_of_clients);
+    }
+
+    #[test]
+    fn every_client_gets_every_message() {
+        let mut trade_web_server = TradeWebServer::new("127.0.0.1:0");
+        let addr = format!("ws://{}", trade_web_server.start_server().unwrap());
+
+        let (mut first_client, _) = connect(&addr).unwrap();
+        let (mut second_client, _) = connect(&addr).unwrap();
+        wait_for_clients(&trade_web_server, 2);
+
+        trade_web_server.add_trade(Trade::fixture("AAPL", 10.5, MINUTE), 2);
+        trade_web_server.add_trade(Trade::fixture("MSFT", 20.0, MINUTE + 1).with_size(5), 2);
+
+        for client in [&mut first_client, &mut second_client] {
+            assert_eq!(read_message(client), WireMessage::trade(&Trade::fixture("AAPL", 10.5, MINUTE), 2));
+            assert_eq!(read_message(client), WireMessage::trade(&Trade::fixture("MSFT", 20.0, MINUTE + 1).with_size(5), 2));
+        }
+
+        first_client.close(None).unwrap();
+        wait_for_clients(&trade_web_server, 1);
+
+        trade_web_server.add_trade(Trade::fixture("TSM", 30.0, MINUTE + 2).with_size(1), 2);
+
+        assert_eq!(read_message(&mut second_client), WireMessage::trade(&Trade::fixture("TSM", 30.0, MINUTE + 2).with_size(1), 2));
+    }
+
+    #[test]
+    fn lagging_readers_skip_to_the_oldest_message() {
+        let trade_web_server = TradeWebServer::new("127.0.0.1:0");
+
+        for i in 0..BUFFER_SIZE + 5 {
+            trade_web_server.broadcast.push(BroadcastMessage::new(None, None, WireMessage::new(WireBody::Lagged { missed: i as u64 })));
+        }
+
+        let (list_of_messages, cursor, missed) = trade_web_server.broadcast.read_from(0, Duration::from_millis(1));
+
+        assert_eq!(missed, 5);
+        assert_eq!(list_of_messages.len(), BUFFER_SIZE);
+        assert_eq!(list_of_messages[0].message, WireMessage::new(WireBody::Lagged { missed: 5 }));
+        assert_eq!(cursor, (BUFFER_SIZE + 5) as u64);
+
+        let (list_of_messages, cursor, missed) = trade_web_server.broadcast.read_from(cursor, Duration::from_millis(1));
+
+        assert!(list_of_messages.is_empty());
+        assert_eq!((cursor, missed), ((BUFFER_SIZE + 5) as u64, 0));
+    }
+
+
+    #[test]
+    fn clients_only_get_their_symbols() {
+        let mut trade_web_server = TradeWebServer::new("127.0.0.1:0");
+        let addr = format!("ws://{}", trade_web_server.start_server().unwrap());
+
+        let failover = FailoverEvent { symbol: "TSM".to_string(), from: ProviderKind::Finnhub, to: ProviderKind::Alpaca, timestamp: MINUTE };
+
+        let (mut client, _) = connect(&addr).unwrap();
+        wait_for_clients(&trade_web_server, 1);
+
+        client.send(Message::text("unsubscribe;*")).unwrap();
+        assert_eq!(read_message(&mut client), WireMessage::new(ack("unsubscribe", &["*"])));
+
+        client.send(Message::text("subscribe;AAPL,BINANCE:*")).unwrap();
+        assert_eq!(read_message(&mut client), WireMessage::new(ack("subscribe", &["AAPL", "BINANCE:*"])));
+
+        trade_web_server.add_trade(Trade::fixture("MSFT", 20.0, MINUTE), 2);
+        trade_web_server.add_trade(Trade::fixture("AAPL", 10.5, MINUTE), 2);
+        trade_web_server.add_trade(Trade::fixture("BINANCE:BTCUSDT", 60_000.0, MINUTE), 2);
+        trade_web_server.add_failover(&failover);
+
+        assert_eq!(read_message(&mut client), WireMessage::trade(&Trade::fixture("AAPL", 10.5, MINUTE), 2));
+        assert_eq!(read_message(&mut client), WireMessage::trade(&Trade::fixture("BINANCE:BTCUSDT", 60_000.0, MINUTE), 2));
+        assert_eq!(read_message(&mut client), WireMessage::failover(&failover));
+
+        client.send(Message::text("subscriptions")).unwrap();
+        assert_eq!(read_message(&mut client), WireMessage::new(ack("subscriptions", &["AAPL", "BINANCE:*"])));
+
+        client.send(Message::text("history;AAPL")).unwrap();
+        assert_eq!(read_message(&mut client), WireMessage::error("history;AAPL", "Unknown request".to_string()));
+    }
+
+    fn bar(symbol: &str, interval_seconds: usize, timestamp: i64, close: f64) -> DataTradeModel {
+        DataTradeModel {
+            timestamp,
+            stock_name: symbol.to_string(),
+            stock_interval: interval_seconds,
+            price_decimals: 2,
+            open_price: Price::from_f64(10.0).unwrap(),
+            high_price: Price::from_f64(close.max(10.0)).unwrap(),
+            low_price: Price::from_f64(close.min(10.0)).unwrap(),
+            close_price: Price::from_f64(close).unwrap(),
+            vwap: Price::from_f64(10.0).unwrap(),
+            volume_moved: 300,
+            num_of_trades: 3,
+        }
+    }
+
+    #[test]
+    fn bar_subscribers_get_the_latest_bar_first() {
+        let mut trade_web_server = TradeWebServer::new("127.0.0.1:0");
+        let addr = format!("ws://{}", trade_web_server.start_server().unwrap());
+
+        trade_web_server.add_candles(&[bar("AAPL", 60, 0, 11.0), bar("AAPL", 1, 59_000, 11.0), bar("MSFT", 60, 0, 20.0)]);
+        trade_web_server.add_candles(&[bar("AAPL", 60, 60_000, 12.5)]);
+
+        let (mut client, _) = connect(&addr).unwrap();
+        wait_for_clients(&trade_web_server, 1);
+
+        client.send(Message::text("unsubscribe;*")).unwrap();
+        assert_eq!(read_message(&mut client), WireMessage::new(ack("unsubscribe", &["*"])));
+
+        client.send(Message::text("subscribe-candles;AAPL@1m")).unwrap();
+        assert_eq!(read_message(&mut client), WireMessage::new(ack("subscribe-candles", &["AAPL@1m"])));
+        assert_eq!(read_message(&mut client), WireMessage::bar(&bar("AAPL", 60, 60_000, 12.5), None));
+
+        trade_web_server.add_candles(&[bar("MSFT", 60, 60_000, 21.0), bar("AAPL", 1, 60_000, 10.0)]);
+        trade_web_server.add_trade(Trade::fixture("AAPL", 10.5, MINUTE), 2);
+        trade_web_server.add_candles(&[bar("AAPL", 60, 120_000, 9.0)]);
+
+        assert_eq!(read_message(&mut client), WireMessage::bar(&bar("AAPL", 60, 120_000, 9.0), None));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn clients_choose_their_encoding() {
+        let mut trade_web_server = TradeWebServer::new("127.0.0.1:0");
+        let addr = trade_web_server.start_server().unwrap();
+
+        let (mut json_client, _) = connect(format!("ws://{}", addr)).unwrap();
+        let (mut binary_client, _) = connect(format!("ws://{}/?encoding=msgpack", addr)).unwrap();
+        wait_for_clients(&trade_web_server, 2);
+
+        let (mut cbor_client, _) = connect(format!("ws://{}/?encoding=cbor", addr)).unwrap();
+        assert_eq!(read_message(&mut cbor_client), WireMessage::error("encoding=cbor", "Unknown encoding: cbor".to_string()));
+        assert!(matches!(cbor_client.read(), Ok(Message::Close(_))));
+
+        trade_web_server.add_trade(Trade::fixture("AAPL", 10.5, MINUTE), 2);
+
+        assert_eq!(json_client.read().unwrap(), WireMessage::trade(&Trade::fixture("AAPL", 10.5, MINUTE), 2).encode(WireEncoding::Json));
+        assert_eq!(binary_client.read().unwrap(), WireMessage::trade(&Trade::fixture("AAPL", 10.5, MINUTE), 2).encode(WireEncoding::MessagePack));
+
+        binary_client.send(Message::text("subscriptions")).unwrap();
+        assert!(matches!(binary_client.read().unwrap(), Message::Binary(_)));
+    }
+}
@@ -0,0 +1,377 @@
+use serde::{Deserialize, Serialize};
+use tungstenite::Message;
+
+use crate::values_store::app_config::WireEncoding;
+use crate::data_analysis::trade::Trade;
+use crate::data_analysis::feed_supervisor::FailoverEvent;
+use crate::database_clients::data_web_client::DataTradeModel;
+
+/*
+    Raised whenever a field changes meaning or is removed, new fields keep the version
+*/
+pub const SCHEMA_VERSION: u32 = 2;
+
+/*
+    Every message sent to the trade server clients and the StockDatastore, one JSON object
+    with the kind of message in "type" and the schema it follows in "schema_version".
+    Prices are decimal strings with the precision of the symbol so they stay exact.
+    With the msgpack encoding the same object goes out as a binary MessagePack map.
+
+    trade:  sn symbol, ex exchange, p price, s size, t unix ms, c conditions, src provider,
+            id trade id of the provider
+    bar:    sq outbox sequence number, si interval seconds, sn symbol, t bar start unix ms,
+            op open, mx high, mn low, cp close, ap vwap, vm volume, nt number of trades
+    failover: symbol moved from one provider to another at timestamp unix ms
+    lagged: missed messages of a client that fell behind
+    ack:    answer to a client request with its patterns, or all of them for a listing
+    error:  request that was not understood and why
+    snapshot: state of the symbols for the query server, see SymbolState
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WireMessage {
+    pub schema_version: u32,
+    #[serde(flatten)]
+    pub body: WireBody,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WireBody {
+    Trade {
+        sn: String,
+        ex: Option<String>,
+        p: String,
+        s: i64,
+        t: i64,
+        c: Vec<String>,
+        src: String,
+        id: Option<String>,
+    },
+    Bar {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sq: Option<u64>,
+        si: usize,
+        sn: String,
+        t: i64,
+        op: String,
+        mx: String,
+        mn: String,
+        cp: String,
+        ap: String,
+        vm: i64,
+        nt: i64,
+    },
+    Failover {
+        symbol: String,
+        from: String,
+        to: String,
+        timestamp: i64,
+    },
+    Lagged {
+        missed: u64,
+    },
+    Ack {
+        request: String,
+        patterns: Vec<String>,
+    },
+    Error {
+        request: String,
+        message: String,
+    },
+    Snapshot {
+        symbols: Vec<SymbolState>,
+    },
+}
+
+/*
+    What the process knows about a symbol, with long names as it is read by people.
+    today is the bar of the current day of the exchange, from midnight in its time zone,
+    current_bars the bars in progress of every interval and ms_since_update the time since
+    the last trade arrived.
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SymbolState {
+    pub symbol: String,
+    pub last_trade: Option<LastTrade>,
+    pub today: Option<BarState>,
+    pub current_bars: Vec<BarState>,
+    pub ms_since_update: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LastTrade {
+    pub price: String,
+    pub size: i64,
+    pub timestamp: i64,
+    pub exchange: Option<String>,
+    pub source: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BarState {
+    pub interval_seconds: usize,
+    pub start: i64,
+    pub open: String,
+    pub high: String,
+    pub low: String,
+    pub close: String,
+    pub vwap: String,
+    pub volume: i64,
+    pub trades: i64,
+}
+
+impl LastTrade {
+    pub fn new(trade: &Trade, price_decimals: u32) -> Self {
+        LastTrade {
+            price: trade.price.format(price_decimals),
+            size: trade.size,
+            timestamp: trade.timestamp_millis(),
+            exchange: trade.exchange.clone(),
+            source: trade.source.to_string(),
+        }
+    }
+}
+
+impl BarState {
+    pub fn new(bar: &DataTradeModel) -> Self {
+        BarState {
+            interval_seconds: bar.stock_interval,
+            start: bar.timestamp,
+            open: bar.open_price.format(bar.price_decimals),
+            high: bar.high_price.format(bar.price_decimals),
+            low: bar.low_price.format(bar.price_decimals),
+            close: bar.close_price.format(bar.price_decimals),
+            vwap: bar.vwap.format(bar.price_decimals),
+            volume: bar.volume_moved,
+            trades: bar.num_of_trades,
+        }
+    }
+}
+
+impl WireMessage {
+    pub fn new(body: WireBody) -> Self {
+        WireMessage { schema_version: SCHEMA_VERSION, body }
+    }
+
+    /*
+        The price has the decimals of the symbol like the prices of its bars
+    */
+    pub fn trade(trade: &Trade, price_decimals: u32) -> Self {
+        WireMessage::new(WireBody::Trade {
+            sn: trade.symbol.clone(),
+            ex: trade.exchange.clone(),
+            p: trade.price.format(price_decimals),
+            s: trade.size,
+            t: trade.timestamp_millis(),
+            c: trade.conditions.clone(),
+            src: trade.source.to_string(),
+            id: trade.trade_id.clone(),
+        })
+    }
+
+    pub fn bar(bar: &DataTradeModel, sequence: Option<u64>) -> Self {
+        WireMessage::new(WireBody::Bar {
+            sq: sequence,
+            si: bar.stock_interval,
+            sn: bar.stock_name.clone(),
+            t: bar.timestamp,
+            op: bar.open_price.format(bar.price_decimals),
+            mx: bar.high_price.format(bar.price_decimals),
+            mn: bar.low_price.format(bar.price_decimals),
+            cp: bar.close_price.format(bar.price_decimals),
+            ap: bar.vwap.format(bar.price_decimals),
+            vm: bar.volume_moved,
+            nt: bar.num_of_trades,
+        })
+    }
+
+    pub fn failover(event: &FailoverEvent) -> Self {
+        WireMessage::new(WireBody::Failover {
+            symbol: event.symbol.clone(),
+            from: event.from.to_string(),
+            to: event.to.to_string(),
+            timestamp: event.timestamp,
+        })
+    }
+
+    pub fn error(request: &str, message: String) -> Self {
+        WireMessage::new(WireBody::Error { request: request.to_string(), message })
+    }
+
+    pub fn to_json(&self) -> String {
+        // only strings and numbers, serializing can not fail
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn to_msgpack(&self) -> Vec<u8> {
+        rmp_serde::to_vec_named(self).unwrap()
+    }
+
+    pub fn encode(&self, encoding: WireEncoding) -> Message {
+        match encoding {
+            WireEncoding::Json => Message::text(self.to_json()),
+            WireEncoding::MessagePack => Message::binary(self.to_msgpack()),
+        }
+    }
+}
+
+/*
+    For messages that were stored as json. Lines of an outbox written before the wire
+    format existed stay text.
+*/
+pub fn encode_json(json: &str, encoding: WireEncoding) -> Message {
+    match (encoding, serde_json::from_str::<WireMessage>(json)) {
+        (WireEncoding::MessagePack, Ok(message)) => Message::binary(message.to_msgpack()),
+        _ => Message::text(json),
+    }
+}
+
+/*
+    "encoding=json" or "encoding=msgpack" in the query of a connection, json without it
+*/
+pub fn encoding_from_query(query: Option<&str>) -> Result<WireEncoding, String> {
+    let encoding = query.unwrap_or("").split('&').find_map(|v| v.strip_prefix("encoding="));
+
+    match encoding {
+        Some(v) => WireEncoding::parse(v),
+        None => Ok(WireEncoding::Json),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tungstenite::Message;
+
+    use crate::values_store::app_config::{ProviderKind, WireEncoding};
+    use crate::data_analysis::price::Price;
+    use crate::data_analysis::trade::Trade;
+    use crate::data_analysis::feed_supervisor::FailoverEvent;
+    use crate::data_parsers::fixtures::ack;
+    use crate::database_clients::data_web_client::DataTradeModel;
+    use crate::database_clients::wire_format::{BarState, LastTrade, SymbolState, WireBody, WireMessage, encode_json, encoding_from_query};
+
+    fn round_trip(message: &WireMessage, json: &str) {
+        assert_eq!(message.to_json(), json);
+        assert_eq!(&serde_json::from_str::<WireMessage>(json).unwrap(), message);
+        assert_eq!(&rmp_serde::from_slice::<WireMessage>(&message.to_msgpack()).unwrap(), message);
+    }
+
+    #[test]
+    fn trades_round_trip() {
+        let trade = Trade {
+            exchange: Some("XNYS".to_string()),
+            conditions: vec!["@".to_string(), "I".to_string()],
+            trade_id: Some("52983525029461".to_string()),
+            ..Trade::fixture("BRK\"B", 412.5, 1_725_636_420_000).with_source(ProviderKind::Alpaca)
+        };
+
+        round_trip(&WireMessage::trade(&trade, 2), concat!(
+            r#"{"schema_version":2,"type":"trade","sn":"BRK\"B","ex":"XNYS","p":"412.50","s":100,"#,
+            r#""t":1725636420000,"c":["@","I"],"src":"alpaca","id":"52983525029461"}"#,
+        ));
+    }
+
+    #[test]
+    fn bars_round_trip() {
+        let bar = DataTradeModel {
+            timestamp: 1_725_636_420_000,
+            stock_name: "AAPL".to_string(),
+            stock_interval: 60,
+            price_decimals: 2,
+            open_price: Price::from_f64(10.0).unwrap(),
+            high_price: Price::from_f64(12.0).unwrap(),
+            low_price: Price::from_f64(9.0).unwrap(),
+            close_price: Price::from_f64(11.25).unwrap(),
+            vwap: Price::from_f64(10.678).unwrap(),
+            volume_moved: 700,
+            num_of_trades: 6,
+        };
+
+        round_trip(&WireMessage::bar(&bar, Some(7)), concat!(
+            r#"{"schema_version":2,"type":"bar","sq":7,"si":60,"sn":"AAPL","t":1725636420000,"#,
+            r#""op":"10.00","mx":"12.00","mn":"9.00","cp":"11.25","ap":"10.68","vm":700,"nt":6}"#,
+        ));
+        round_trip(&WireMessage::bar(&bar, None), concat!(
+            r#"{"schema_version":2,"type":"bar","si":60,"sn":"AAPL","t":1725636420000,"#,
+            r#""op":"10.00","mx":"12.00","mn":"9.00","cp":"11.25","ap":"10.68","vm":700,"nt":6}"#,
+        ));
+    }
+
+    #[test]
+    fn failovers_round_trip() {
+        let event = FailoverEvent { symbol: "AAPL".to_string(), from: ProviderKind::Finnhub, to: ProviderKind::Alpaca, timestamp: 1_725_636_450_001 };
+
+        round_trip(&WireMessage::failover(&event), concat!(
+            r#"{"schema_version":2,"type":"failover","symbol":"AAPL","from":"finnhub","to":"alpaca","#,
+            r#""timestamp":1725636450001}"#,
+        ));
+    }
+
+    #[test]
+    fn acks_round_trip() {
+        round_trip(&WireMessage::new(ack("subscribe", &["AAPL", "BINANCE:*"])), r#"{"schema_version":2,"type":"ack","request":"subscribe","patterns":["AAPL","BINANCE:*"]}"#);
+        round_trip(&WireMessage::new(ack("candle-subscriptions", &[])), r#"{"schema_version":2,"type":"ack","request":"candle-subscriptions","patterns":[]}"#);
+    }
+
+    #[test]
+    fn errors_round_trip() {
+        round_trip(&WireMessage::error("history;AAPL", "Unknown request".to_string()), r#"{"schema_version":2,"type":"error","request":"history;AAPL","message":"Unknown request"}"#);
+    }
+
+    #[test]
+    fn lagged_round_trips() {
+        round_trip(&WireMessage::new(WireBody::Lagged { missed: 12 }), r#"{"schema_version":2,"type":"lagged","missed":12}"#);
+    }
+
+    #[test]
+    fn snapshots_round_trip() {
+        let symbol_state = SymbolState {
+            symbol: "AAPL".to_string(),
+            last_trade: Some(LastTrade { price: "10.50".to_string(), size: 10, timestamp: 1_725_636_421_000, exchange: None, source: "finnhub".to_string() }),
+            today: None,
+            current_bars: vec![BarState {
+                interval_seconds: 60,
+                start: 1_725_636_420_000,
+                open: "10.50".to_string(),
+                high: "10.50".to_string(),
+                low: "10.50".to_string(),
+                close: "10.50".to_string(),
+                vwap: "10.50".to_string(),
+                volume: 10,
+                trades: 1,
+            }],
+            ms_since_update: Some(1_500),
+        };
+
+        round_trip(&WireMessage::new(WireBody::Snapshot { symbols: vec![symbol_state] }), concat!(
+            r#"{"schema_version":2,"type":"snapshot","symbols":[{"symbol":"AAPL","#,
+            r#""last_trade":{"price":"10.50","size":10,"timestamp":1725636421000,"exchange":null,"source":"finnhub"},"#,
+            r#""today":null,"current_bars":[{"interval_seconds":60,"start":1725636420000,"open":"10.50","high":"10.50","#,
+            r#""low":"10.50","close":"10.50","vwap":"10.50","volume":10,"trades":1}],"ms_since_update":1500}]}"#,
+        ));
+    }
+
+    #[test]
+    fn newer_fields_are_ignored() {
+        let json = r#"{"schema_version":2,"type":"lagged","missed":3,"since":5}"#;
+
+        assert_eq!(serde_json::from_str::<WireMessage>(json).unwrap(), WireMessage::new(WireBody::Lagged { missed: 3 }));
+    }
+
+    #[test]
+    fn stored_json_is_sent_in_the_chosen_encoding() {
+        let json = r#"{"schema_version":2,"type":"lagged","missed":3}"#;
+
+        assert_eq!(encode_json(json, WireEncoding::Json), Message::text(json));
+        assert_eq!(encode_json(json, WireEncoding::MessagePack), WireMessage::new(WireBody::Lagged { missed: 3 }).encode(WireEncoding::MessagePack));
+        assert_eq!(encode_json("{\"si\": 60}", WireEncoding::MessagePack), Message::text("{\"si\": 60}"));
+    }
+
+    #[test]
+    fn encoding_is_chosen_by_the_query() {
+        assert_eq!(encoding_from_query(None), Ok(WireEncoding::Json));
+        assert_eq!(encoding_from_query(Some("token=1&encoding=msgpack")), Ok(WireEncoding::MessagePack));
+        assert_eq!(encoding_from_query(Some("encoding=json")), Ok(WireEncoding::Json));
+        assert!(encoding_from_query(Some("encoding=cbor")).is_err());
+    }
+}
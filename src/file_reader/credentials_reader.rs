@@ -35,7 +35,7 @@ impl CredentialsReader {
         Ok(data.into_iter().map(|byte| byte as char).collect())
     }
 
-    fn parse_xml_file(&self, raw_data: &Vec<char>) -> Result<HashMap<String, String>, Box<dyn error::Error + 'static>> {
+    fn parse_xml_file(&self, raw_data: &[char]) -> Result<HashMap<String, String>, Box<dyn error::Error + 'static>> {
         let n: usize = raw_data.len();
 
         let mut tmp: String = String::new();
@@ -58,7 +58,7 @@ impl CredentialsReader {
             if raw_data[i] == '<' {
                 if i == n-1 { panic!("There is an open < at the last position"); }
 
-                if entry_desc_stack.len() > 0 {
+                if !entry_desc_stack.is_empty() {
                     entry_stack.push(tmp.to_owned());
                 }
 
@@ -68,7 +68,7 @@ impl CredentialsReader {
                     parse_status = 1;
                     i += 1;
                 } else {
-                    if entry_desc_stack.len() == 0 { panic!("Found closing line without the corresponding object at line: {}", current_line); }
+                    if entry_desc_stack.is_empty() { panic!("Found closing line without the corresponding object at line: {}", current_line); }
 
                     parse_status = 2;
                     i += 2;
@@ -5,50 +5,225 @@ mod database_clients;
 mod data_parsers;
 mod data_analysis;
 
+use std::env;
+use std::thread;
+use std::sync::{Arc, Mutex};
+use std::process;
+use std::time::Duration;
+
 use crate::values_store::credentials_store::CredentialsStore;
+use crate::values_store::app_config::{AppConfig, ProviderKind, USAGE};
 use crate::database_clients::data_web_client::DataWebClient;
 use crate::database_clients::trade_web_server::TradeWebServer;
+use crate::database_clients::trade_journal::{TradeJournal, start_journal};
+use crate::database_clients::candle_outbox::CandleOutbox;
+use crate::database_clients::query_server::QueryServer;
 use crate::data_analysis::stock_analysis::StockAnalyserWeb;
+use crate::data_analysis::trade_consolidator::TradeConsolidator;
+use crate::data_analysis::feed_supervisor::{FeedSupervisor, start_supervisor};
+use crate::data_analysis::clock::now_millis;
+use crate::data_analysis::market_hours::MarketHours;
 use crate::web_clients::eodhd::EodhdClient;
 use crate::web_clients::finnhub::FinnhubClient;
 use crate::web_clients::alpaca::AlpacaClient;
 use crate::web_clients::twelve::TwelveClient;
 use crate::web_clients::tiingo::TiingoClient;
+use crate::web_clients::session_driver::SessionDriver;
+use crate::web_clients::backfill::{Backfill, history_provider};
+use crate::web_clients::replay::{ReplayClient, read_replay};
+use crate::web_clients::frame_recorder::FrameRecorder;
 
 
 fn main() {
-    let credentials_store:CredentialsStore = CredentialsStore::new();
-    let data_web_client:DataWebClient = DataWebClient::new("ws://localhost:9003");
-    let stock_config_list:Vec<String> = data_web_client.start_client();
+    let app_config:AppConfig = match AppConfig::from_args(env::args().skip(1)) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(2);
+        },
+    };
+
+    if app_config.show_help {
+        println!("{}", USAGE);
+        return;
+    }
+
+    let credentials_store:CredentialsStore = CredentialsStore::new(&app_config.credentials);
+    let mut data_web_client:DataWebClient = DataWebClient::new(&app_config.data_store, app_config.queue_size, app_config.queue_overflow);
+    data_web_client.set_encoding(app_config.data_store_encoding);
+
+    if let Some(outbox_dir) = &app_config.outbox_dir {
+        match CandleOutbox::open(outbox_dir) {
+            Ok(v) => data_web_client.set_candle_outbox(v),
+            Err(e) => {
+                eprintln!("Error opening candle outbox {}: {}", outbox_dir, e);
+                process::exit(1);
+            },
+        };
+    }
+
+    let data_store_stock_list:Option<Vec<String>> = match data_web_client.start_client(app_config.startup_attempts) {
+        Ok(v) => Some(v),
+        Err(e) => {
+            eprintln!("StockDatastore unavailable: {}", e);
+            None
+        },
+    };
+
+    let stock_config_list:Vec<String> = match (app_config.symbols.clone(), data_store_stock_list, app_config.fallback_symbols.clone()) {
+        (Some(v), _, _) => v,
+        (None, Some(v), _) => v,
+        (None, None, Some(v)) => {
+            println!("Subscribing to the fallback symbols, bars are sent once the StockDatastore is back");
+            v
+        },
+        (None, None, None) => {
+            eprintln!("No symbols to subscribe to, start the StockDatastore or pass --symbols or --fallback-symbols");
+            process::exit(1);
+        },
+    };
+
+    let trade_web_server:TradeWebServer = TradeWebServer::new(&app_config.trade_server);
+    if let Err(e) = trade_web_server.start_server() {
+        eprintln!("Error starting trade server on {}: {}", app_config.trade_server, e);
+        process::exit(1);
+    }
+
+    let trade_consolidator:TradeConsolidator = TradeConsolidator::new(app_config.dedup, app_config.providers[0]);
+    let data_store_client:DataWebClient = data_web_client.clone();
+    let mut stock_analysis_web:StockAnalyserWeb = StockAnalyserWeb::new(
+        data_web_client,
+        trade_web_server.clone(),
+        trade_consolidator,
+        app_config.price_precision.clone(),
+        app_config.candle_intervals.clone(),
+        app_config.bar_policy,
+    );
+
+    if let Some(journal_dir) = &app_config.journal_dir {
+        stock_analysis_web.set_trade_journal(start_journal(TradeJournal::new(journal_dir)));
+    }
+
+    if app_config.is_replay() {
+        run_replay(&app_config, stock_analysis_web, &data_store_client);
+        return;
+    }
+
+    let query_server:QueryServer = QueryServer::new(&app_config.query_server, stock_analysis_web.clone());
+    if let Err(e) = query_server.start_server() {
+        eprintln!("Error starting query server on {}: {}", app_config.query_server, e);
+        process::exit(1);
+    }
+
+    stock_analysis_web.start_candle_thread();
+
+    if let Some(backup) = app_config.backup {
+        let mut feed_supervisor:FeedSupervisor = FeedSupervisor::new(
+            app_config.providers[0],
+            backup,
+            app_config.stall_threshold_seconds * 1000,
+            MarketHours::us_equities(),
+        );
+
+        for stock in stock_config_list.iter() {
+            feed_supervisor.watch(stock, now_millis());
+        }
 
-    let trade_web_server:TradeWebServer = TradeWebServer::new("localhost:9010");
-    trade_web_server.start_server();
+        let feed_supervisor = Arc::new(Mutex::new(feed_supervisor));
 
-    let stock_analysis_web:StockAnalyserWeb = StockAnalyserWeb::new(data_web_client, trade_web_server);
+        stock_analysis_web.set_feed_supervisor(feed_supervisor.clone());
+        start_supervisor(feed_supervisor, trade_web_server);
+    }
 
-    let client_selection:usize = 0;
+    if app_config.backfill_minutes > 0 {
+        match history_provider(app_config.providers[0], &credentials_store, app_config.backfill_url.as_deref()) {
+            Some(v) => {
+                let backfill:Backfill = Backfill::new(app_config.backfill_minutes * 60_000);
+                backfill.run(v.as_ref(), &mut stock_analysis_web, &stock_config_list, now_millis());
+            },
+            None => println!("No history api for {}, skipping backfill", app_config.providers[0]),
+        };
+    }
 
-    match client_selection {
-        0 => {
+    let mut provider_threads = Vec::new();
+
+    for provider in app_config.providers_to_run().into_iter() {
+        let credentials_store = credentials_store.clone();
+        let stock_analysis_web = stock_analysis_web.clone();
+        let stock_config_list = stock_config_list.clone();
+        let frame_recorder:Option<FrameRecorder> = app_config.record_frames.as_ref().map(|v| FrameRecorder::start(v, provider));
+        let tiingo_quotes = app_config.tiingo_quotes;
+
+        println!("Starting {} provider", provider);
+
+        provider_threads.push(thread::spawn(move || {
+            run_provider(provider, credentials_store, stock_analysis_web, &stock_config_list, frame_recorder, tiingo_quotes);
+        }));
+    }
+
+    for provider_thread in provider_threads {
+        let _ = provider_thread.join();
+    }
+}
+
+fn run_provider(provider: ProviderKind, credentials_store: CredentialsStore, stock_analysis_web: StockAnalyserWeb, stock_config_list: &[String], frame_recorder: Option<FrameRecorder>, tiingo_quotes: bool) {
+    let mut session_driver:SessionDriver = SessionDriver::new();
+
+    if let Some(v) = frame_recorder {
+        session_driver.set_frame_recorder(v);
+    }
+
+    match provider {
+        ProviderKind::Finnhub => {
             let mut finnhub_client:FinnhubClient = FinnhubClient::new(credentials_store, stock_analysis_web);
-            finnhub_client.print_hello(&stock_config_list);
+            session_driver.run(&mut finnhub_client, stock_config_list);
         },
-        1 => {
+        ProviderKind::Eodhd => {
             let mut eodhd_client:EodhdClient = EodhdClient::new(credentials_store, stock_analysis_web);
-            eodhd_client.print_hello(&stock_config_list);
+            session_driver.run(&mut eodhd_client, stock_config_list);
         },
-        2 => {
+        ProviderKind::Alpaca => {
             let mut alpaca_client:AlpacaClient = AlpacaClient::new(credentials_store, stock_analysis_web);
-            alpaca_client.print_hello(&stock_config_list);
+            session_driver.run(&mut alpaca_client, stock_config_list);
         },
-        3 => {
+        ProviderKind::Twelve => {
             let mut twelve_client:TwelveClient = TwelveClient::new(credentials_store, stock_analysis_web);
-            twelve_client.print_hello(&stock_config_list);
-        }
-        4 => {
+            session_driver.run(&mut twelve_client, stock_config_list);
+        },
+        ProviderKind::Tiingo => {
             let mut tiingo_client:TiingoClient = TiingoClient::new(credentials_store, stock_analysis_web);
-            tiingo_client.print_hello(&stock_config_list);
-        }
-        _ => (),
+            tiingo_client.set_include_quotes(tiingo_quotes);
+            session_driver.run(&mut tiingo_client, stock_config_list);
+        },
+        // replays run on the main thread, see run_replay
+        ProviderKind::Replay => (),
+    };
+}
+
+fn run_replay(app_config: &AppConfig, stock_analysis_web: StockAnalyserWeb, data_web_client: &DataWebClient) {
+    let replay_path = app_config.replay.clone().unwrap_or_default();
+
+    let list_of_events = match read_replay(&replay_path, app_config.replay_from, app_config.replay_to) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Error reading replay {}: {}", replay_path, e);
+            process::exit(1);
+        },
     };
+
+    println!("Replaying {} events from {}", list_of_events.len(), replay_path);
+
+    let mut replay_client:ReplayClient = ReplayClient::new(stock_analysis_web, app_config.replay_pace);
+    replay_client.set_tiingo_quotes(app_config.tiingo_quotes);
+    let num_of_events = replay_client.run(list_of_events);
+
+    println!("Replayed {} events with market data", num_of_events);
+
+    if !data_web_client.wait_until_sent(Duration::from_secs(30)) {
+        println!("Not every candle reached the StockDatastore");
+    }
+
+    if data_web_client.dropped_candles() > 0 {
+        println!("{} candles were dropped on a full queue", data_web_client.dropped_candles());
+    }
 }
@@ -0,0 +1,642 @@
+use std::fmt;
+
+use chrono::DateTime;
+
+use crate::values_store::price_precision::{PricePrecision, parse_decimals};
+use crate::values_store::candle_intervals::{CandleIntervals, parse_intervals};
+
+pub const USAGE: &str = "Usage: stockwatch [OPTIONS]
+
+Options:
+    --provider <finnhub|eodhd|alpaca|twelve|tiingo>   Market data providers, comma separated. The first one is the
+                                                      primary feed (default: finnhub)
+    --provider replay --replay <path>                 Replay a frame recording (file) or a trade journal (directory)
+                                                      instead of connecting to a vendor
+    --replay-speed <original|fast|factor>             Pace of the replay, e.g. 10 for ten times faster (default: original)
+    --replay-from <RFC3339>                           Start of the replayed range of a journal
+    --replay-to <RFC3339>                             End of the replayed range of a journal
+    --dedup <first-arrival|primary-secondary>         How duplicate trades of several providers are resolved
+                                                      (default: first-arrival)
+    --backup <provider>                               Provider to fail over to when the primary goes quiet
+    --stall-threshold <seconds>                       Seconds without trades of a symbol from the primary before the
+                                                      symbol fails over, and without a gap before it is switched
+                                                      back (default: 30)
+    --data-store <ws://...>                           Address of the StockDatastore (default: ws://localhost:9003)
+    --queue-size <bars>                               Bars held for the StockDatastore while it is slow or away
+                                                      (default: 100000)
+    --queue-overflow <block|drop-oldest|coalesce>     What happens to new bars when that queue is full
+                                                      (default: drop-oldest)
+    --outbox-dir <path>                               Keep the bars for the StockDatastore on disk in this
+                                                      directory until it acknowledged them
+    --data-store-encoding <json|msgpack>              Encoding of the bars sent to the StockDatastore
+                                                      (default: json)
+    --trade-server <host:port>                        Address the trade server listens on (default: localhost:9010)
+    --query-server <host:port>                        Address of the http api for the state of the symbols,
+                                                      GET /snapshot or /snapshot/<symbol> (default: localhost:9011)
+    --credentials <path>                              Path to the api keys (default: ./credentials/apikeys.xml)
+    --symbols <AAPL,MSFT,...>                         Symbols to subscribe to instead of the list of the StockDatastore
+    --fallback-symbols <AAPL,MSFT,...>                Symbols to subscribe to if the StockDatastore can't be reached
+                                                      at startup
+    --startup-attempts <count>                        Attempts to reach the StockDatastore at startup (default: 5)
+    --price-decimals <0-8>                            Decimals prices are rounded to (default: 4)
+    --symbol-decimals <SYMBOL=DECIMALS,...>           Decimals for single symbols, e.g. BINANCE:BTCUSDT=8
+    --intervals <5s,15m,1h,4h,1d,...>                 Bars built besides the 1 second bars, aligned to the clock
+                                                      (default: 10s,1m,5m,10m)
+    --symbol-intervals <SYMBOL=INTERVALS;...>         Bars for single symbols, e.g. AAPL=5s,1h;MSFT=1d
+    --bar-grace-ms <millis>                           How long a bar stays open after its end for delayed trades
+                                                      (default: 1000)
+    --late-trades <drop|amend>                        What happens to trades of a bar that was already sent
+                                                      (default: drop)
+    --backfill-minutes <minutes>                      Minutes of 1 minute bars loaded from the primary provider's
+                                                      REST api on startup, 0 disables it (default: 0)
+    --backfill-url <http://...>                       Address of the history api instead of the vendor's
+    --journal-dir <path>                              Write every valid trade to a daily journal in this directory
+    --record-frames <path>                            Capture the raw frames of every provider in this directory
+    --tiingo-quotes                                   Count Tiingo quotes as trades at the mid price without volume
+    --help                                            Print this message";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProviderKind {
+    Finnhub,
+    Eodhd,
+    Alpaca,
+    Twelve,
+    Tiingo,
+    Replay,
+}
+
+impl ProviderKind {
+    pub fn parse(raw_value: &str) -> Result<Self, String> {
+        match raw_value.to_lowercase().as_str() {
+            "finnhub" => Ok(ProviderKind::Finnhub),
+            "eodhd" => Ok(ProviderKind::Eodhd),
+            "alpaca" => Ok(ProviderKind::Alpaca),
+            "twelve" => Ok(ProviderKind::Twelve),
+            "tiingo" => Ok(ProviderKind::Tiingo),
+            "replay" => Ok(ProviderKind::Replay),
+            _ => Err(format!("Unknown provider: {}", raw_value)),
+        }
+    }
+}
+
+impl fmt::Display for ProviderKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ProviderKind::Finnhub => "finnhub",
+            ProviderKind::Eodhd => "eodhd",
+            ProviderKind::Alpaca => "alpaca",
+            ProviderKind::Twelve => "twelve",
+            ProviderKind::Tiingo => "tiingo",
+            ProviderKind::Replay => "replay",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/*
+    first-arrival: whichever provider reports a trade first wins, later copies are dropped
+    primary-secondary: secondary providers are only used while the primary is silent for a symbol
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DedupRule {
+    FirstArrival,
+    PrimarySecondary,
+}
+
+impl DedupRule {
+    pub fn parse(raw_value: &str) -> Result<Self, String> {
+        match raw_value.to_lowercase().as_str() {
+            "first-arrival" => Ok(DedupRule::FirstArrival),
+            "primary-secondary" => Ok(DedupRule::PrimarySecondary),
+            _ => Err(format!("Unknown dedup rule: {}", raw_value)),
+        }
+    }
+}
+
+/*
+    drop: the trade is left out of bars that were already sent, longer bars still open take it
+    amend: the last sent bar of an interval is updated and sent again, older bars drop the trade
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LateTradePolicy {
+    Drop,
+    Amend,
+}
+
+impl LateTradePolicy {
+    pub fn parse(raw_value: &str) -> Result<Self, String> {
+        match raw_value.to_lowercase().as_str() {
+            "drop" => Ok(LateTradePolicy::Drop),
+            "amend" => Ok(LateTradePolicy::Amend),
+            _ => Err(format!("Unknown late trade policy: {}", raw_value)),
+        }
+    }
+}
+
+/*
+    block: the candle thread waits until the StockDatastore took bars off the queue
+    drop-oldest: the oldest queued bar is thrown away
+    coalesce: an amended bar replaces its queued version with the same symbol, interval and
+    start, any other bar throws away the oldest one
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OverflowPolicy {
+    Block,
+    DropOldest,
+    Coalesce,
+}
+
+impl OverflowPolicy {
+    pub fn parse(raw_value: &str) -> Result<Self, String> {
+        match raw_value.to_lowercase().as_str() {
+            "block" => Ok(OverflowPolicy::Block),
+            "drop-oldest" => Ok(OverflowPolicy::DropOldest),
+            "coalesce" => Ok(OverflowPolicy::Coalesce),
+            _ => Err(format!("Unknown queue overflow policy: {}", raw_value)),
+        }
+    }
+}
+
+/*
+    json: text messages, msgpack: binary MessagePack messages with the same fields
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WireEncoding {
+    Json,
+    MessagePack,
+}
+
+impl WireEncoding {
+    pub fn parse(raw_value: &str) -> Result<Self, String> {
+        match raw_value.to_lowercase().as_str() {
+            "json" => Ok(WireEncoding::Json),
+            "msgpack" => Ok(WireEncoding::MessagePack),
+            _ => Err(format!("Unknown encoding: {}", raw_value)),
+        }
+    }
+}
+
+impl fmt::Display for WireEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            WireEncoding::Json => "json",
+            WireEncoding::MessagePack => "msgpack",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/*
+    original: events are replayed with the gaps they were recorded with
+    accelerated: the gaps are divided by the factor
+    as-fast-as-possible: no waiting at all
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplayPace {
+    Original,
+    Accelerated(f64),
+    AsFastAsPossible,
+}
+
+impl ReplayPace {
+    pub fn parse(raw_value: &str) -> Result<Self, String> {
+        match raw_value.to_lowercase().as_str() {
+            "original" => Ok(ReplayPace::Original),
+            "fast" => Ok(ReplayPace::AsFastAsPossible),
+            other => match other.trim_end_matches('x').parse::<f64>() {
+                Ok(1.0) => Ok(ReplayPace::Original),
+                Ok(v) if v > 0.0 && v.is_finite() => Ok(ReplayPace::Accelerated(v)),
+                _ => Err(format!("Invalid replay speed: {}", raw_value)),
+            },
+        }
+    }
+}
+
+/*
+    A bar is sent grace_ms after its end, trades for it that arrive later are handled by late_trades
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BarPolicy {
+    pub grace_ms: i64,
+    pub late_trades: LateTradePolicy,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct AppConfig {
+    pub providers: Vec<ProviderKind>,
+    pub dedup: DedupRule,
+    pub backup: Option<ProviderKind>,
+    pub stall_threshold_seconds: i64,
+    pub data_store: String,
+    pub queue_size: usize,
+    pub queue_overflow: OverflowPolicy,
+    pub outbox_dir: Option<String>,
+    pub data_store_encoding: WireEncoding,
+    pub trade_server: String,
+    pub query_server: String,
+    pub credentials: String,
+    pub symbols: Option<Vec<String>>,
+    pub fallback_symbols: Option<Vec<String>>,
+    pub startup_attempts: u32,
+    pub price_precision: PricePrecision,
+    pub candle_intervals: CandleIntervals,
+    pub bar_policy: BarPolicy,
+    pub backfill_minutes: i64,
+    pub backfill_url: Option<String>,
+    pub journal_dir: Option<String>,
+    pub record_frames: Option<String>,
+    pub replay: Option<String>,
+    pub replay_pace: ReplayPace,
+    pub replay_from: Option<i64>,
+    pub replay_to: Option<i64>,
+    pub tiingo_quotes: bool,
+    pub show_help: bool,
+}
+
+impl AppConfig {
+    pub fn new() -> Self {
+        AppConfig {
+            providers: vec![ProviderKind::Finnhub],
+            dedup: DedupRule::FirstArrival,
+            backup: None,
+            stall_threshold_seconds: 30,
+            data_store: "ws://localhost:9003".to_string(),
+            queue_size: 100_000,
+            queue_overflow: OverflowPolicy::DropOldest,
+            outbox_dir: None,
+            data_store_encoding: WireEncoding::Json,
+            trade_server: "localhost:9010".to_string(),
+            query_server: "localhost:9011".to_string(),
+            credentials: "./credentials/apikeys.xml".to_string(),
+            symbols: None,
+            fallback_symbols: None,
+            startup_attempts: 5,
+            price_precision: PricePrecision::new(4),
+            candle_intervals: CandleIntervals::new(vec![10, 60, 300, 600]),
+            bar_policy: BarPolicy {
+                grace_ms: 1000,
+                late_trades: LateTradePolicy::Drop,
+            },
+            backfill_minutes: 0,
+            backfill_url: None,
+            journal_dir: None,
+            record_frames: None,
+            replay: None,
+            replay_pace: ReplayPace::Original,
+            replay_from: None,
+            replay_to: None,
+            tiingo_quotes: false,
+            show_help: false,
+        }
+    }
+
+    /*
+        Parses the command line arguments without the program name.
+        Accepts both "--key value" and "--key=value".
+    */
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut app_config = AppConfig::new();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let (key, inline_value) = match arg.split_once('=') {
+                Some((k, v)) => (k.to_string(), Some(v.to_string())),
+                None => (arg, None),
+            };
+
+            if key == "--help" || key == "-h" {
+                app_config.show_help = true;
+                continue;
+            }
+
+            if key == "--tiingo-quotes" {
+                app_config.tiingo_quotes = true;
+                continue;
+            }
+
+            let value = match inline_value.or_else(|| args.next()) {
+                Some(v) => v,
+                None => return Err(format!("Missing value for {}", key)),
+            };
+
+            match key.as_str() {
+                "--provider" => app_config.providers = parse_providers(&value)?,
+                "--dedup" => app_config.dedup = DedupRule::parse(&value)?,
+                "--backup" => app_config.backup = Some(ProviderKind::parse(&value)?),
+                "--stall-threshold" => app_config.stall_threshold_seconds = parse_seconds(&value)?,
+                "--data-store" => app_config.data_store = value,
+                "--trade-server" => app_config.trade_server = value,
+                "--query-server" => app_config.query_server = value,
+                "--credentials" => app_config.credentials = value,
+                "--symbols" => app_config.symbols = Some(split_list(&value)),
+                "--fallback-symbols" => app_config.fallback_symbols = Some(split_list(&value)),
+                "--startup-attempts" => app_config.startup_attempts = parse_attempts(&value)?,
+                "--price-decimals" => app_config.price_precision.set_default(parse_decimals(&value)?),
+                "--symbol-decimals" => app_config.price_precision.parse_symbols(&value)?,
+                "--intervals" => app_config.candle_intervals.set_default(parse_intervals(&value)?),
+                "--symbol-intervals" => app_config.candle_intervals.parse_symbols(&value)?,
+                "--bar-grace-ms" => app_config.bar_policy.grace_ms = parse_millis(&value)?,
+                "--queue-size" => app_config.queue_size = parse_queue_size(&value)?,
+                "--queue-overflow" => app_config.queue_overflow = OverflowPolicy::parse(&value)?,
+                "--data-store-encoding" => app_config.data_store_encoding = WireEncoding::parse(&value)?,
+                "--outbox-dir" => app_config.outbox_dir = Some(value),
+                "--late-trades" => app_config.bar_policy.late_trades = LateTradePolicy::parse(&value)?,
+                "--backfill-minutes" => app_config.backfill_minutes = parse_minutes(&value)?,
+                "--backfill-url" => app_config.backfill_url = Some(value),
+                "--journal-dir" => app_config.journal_dir = Some(value),
+                "--record-frames" => app_config.record_frames = Some(value),
+                "--replay" => app_config.replay = Some(value),
+                "--replay-speed" => app_config.replay_pace = ReplayPace::parse(&value)?,
+                "--replay-from" => app_config.replay_from = Some(parse_time(&value)?),
+                "--replay-to" => app_config.replay_to = Some(parse_time(&value)?),
+                _ => return Err(format!("Unknown argument: {}", key)),
+            }
+        }
+
+        if app_config.backup == Some(app_config.providers[0]) {
+            return Err("The backup provider has to differ from the primary".to_string());
+        }
+
+        if app_config.backup == Some(ProviderKind::Replay) {
+            return Err("A replay can't be a backup provider".to_string());
+        }
+
+        if app_config.is_replay() {
+            if app_config.providers.len() > 1 || app_config.backup.is_some() {
+                return Err("A replay can't be combined with other providers".to_string());
+            }
+
+            if app_config.replay.is_none() {
+                return Err("Missing --replay <path> for the replay provider".to_string());
+            }
+        }
+
+        Ok(app_config)
+    }
+
+    pub fn is_replay(&self) -> bool {
+        self.providers.contains(&ProviderKind::Replay)
+    }
+
+    /*
+        Primary and secondary providers followed by the backup, each provider once
+    */
+    pub fn providers_to_run(&self) -> Vec<ProviderKind> {
+        let mut providers = self.providers.clone();
+
+        if let Some(backup) = self.backup {
+            if !providers.contains(&backup) {
+                providers.push(backup);
+            }
+        }
+
+        providers
+    }
+}
+
+fn parse_seconds(raw_value: &str) -> Result<i64, String> {
+    match raw_value.parse::<i64>() {
+        Ok(v) if v > 0 => Ok(v),
+        _ => Err(format!("Invalid number of seconds: {}", raw_value)),
+    }
+}
+
+fn parse_time(raw_value: &str) -> Result<i64, String> {
+    match DateTime::parse_from_rfc3339(raw_value) {
+        Ok(v) => Ok(v.timestamp_millis()),
+        Err(_) => Err(format!("Invalid time, expected RFC3339 like 2024-09-06T13:30:00Z: {}", raw_value)),
+    }
+}
+
+fn parse_minutes(raw_value: &str) -> Result<i64, String> {
+    match raw_value.parse::<i64>() {
+        Ok(v) if v >= 0 => Ok(v),
+        _ => Err(format!("Invalid number of minutes: {}", raw_value)),
+    }
+}
+
+fn parse_millis(raw_value: &str) -> Result<i64, String> {
+    match raw_value.parse::<i64>() {
+        Ok(v) if v >= 0 => Ok(v),
+        _ => Err(format!("Invalid number of milliseconds: {}", raw_value)),
+    }
+}
+
+fn parse_attempts(raw_value: &str) -> Result<u32, String> {
+    match raw_value.parse::<u32>() {
+        Ok(v) if v > 0 => Ok(v),
+        _ => Err(format!("Invalid number of attempts: {}", raw_value)),
+    }
+}
+
+fn parse_queue_size(raw_value: &str) -> Result<usize, String> {
+    match raw_value.parse::<usize>() {
+        Ok(v) if v > 0 => Ok(v),
+        _ => Err(format!("Invalid queue size: {}", raw_value)),
+    }
+}
+
+fn parse_providers(raw_value: &str) -> Result<Vec<ProviderKind>, String> {
+    let mut providers: Vec<ProviderKind> = Vec::new();
+
+    for name in split_list(raw_value).iter() {
+        let provider = ProviderKind::parse(name)?;
+
+        if providers.contains(&provider) {
+            return Err(format!("Provider listed twice: {}", provider));
+        }
+
+        providers.push(provider);
+    }
+
+    match providers.is_empty() {
+        true => Err("No provider given".to_string()),
+        false => Ok(providers),
+    }
+}
+
+fn split_list(raw_value: &str) -> Vec<String> {
+    raw_value.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::values_store::app_config::{AppConfig, DedupRule, ProviderKind, LateTradePolicy, OverflowPolicy, ReplayPace, WireEncoding};
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn defaults_without_arguments() {
+        assert_eq!(AppConfig::from_args(args(&[])).unwrap(), AppConfig::new());
+    }
+
+    #[test]
+    fn parses_all_options() {
+        let app_config = AppConfig::from_args(args(&[
+            "--provider", "alpaca",
+            "--data-store=ws://store:9100",
+            "--trade-server", "0.0.0.0:9011",
+            "--query-server", "0.0.0.0:9012",
+            "--credentials", "/etc/stockwatch/apikeys.xml",
+            "--symbols", "AAPL, MSFT,,TSM",
+            "--journal-dir", "/var/lib/stockwatch/journal",
+            "--record-frames", "/var/lib/stockwatch/frames",
+            "--outbox-dir", "/var/lib/stockwatch/outbox",
+        ])).unwrap();
+
+        assert_eq!(app_config.providers, vec![ProviderKind::Alpaca]);
+        assert_eq!(app_config.data_store, "ws://store:9100");
+        assert_eq!(app_config.trade_server, "0.0.0.0:9011");
+        assert_eq!(app_config.query_server, "0.0.0.0:9012");
+        assert_eq!(app_config.credentials, "/etc/stockwatch/apikeys.xml");
+        assert_eq!(app_config.symbols, Some(args(&["AAPL", "MSFT", "TSM"])));
+        assert_eq!(app_config.journal_dir, Some("/var/lib/stockwatch/journal".to_string()));
+        assert_eq!(app_config.record_frames, Some("/var/lib/stockwatch/frames".to_string()));
+        assert_eq!(app_config.outbox_dir, Some("/var/lib/stockwatch/outbox".to_string()));
+    }
+
+    #[test]
+    fn parses_several_providers() {
+        let app_config = AppConfig::from_args(args(&["--provider", "finnhub,alpaca", "--dedup", "primary-secondary"])).unwrap();
+
+        assert_eq!(app_config.providers, vec![ProviderKind::Finnhub, ProviderKind::Alpaca]);
+        assert_eq!(app_config.dedup, DedupRule::PrimarySecondary);
+    }
+
+    #[test]
+    fn backup_is_started_once() {
+        let app_config = AppConfig::from_args(args(&["--provider", "finnhub,alpaca", "--backup", "alpaca", "--stall-threshold", "10"])).unwrap();
+
+        assert_eq!(app_config.backup, Some(ProviderKind::Alpaca));
+        assert_eq!(app_config.stall_threshold_seconds, 10);
+        assert_eq!(app_config.providers_to_run(), vec![ProviderKind::Finnhub, ProviderKind::Alpaca]);
+
+        assert!(AppConfig::from_args(args(&["--backup", "finnhub"])).is_err());
+        assert!(AppConfig::from_args(args(&["--backup", "replay", "--replay", "./journal"])).is_err());
+        assert!(AppConfig::from_args(args(&["--stall-threshold", "-1"])).is_err());
+    }
+
+    #[test]
+    fn parses_price_precision() {
+        let app_config = AppConfig::from_args(args(&["--symbol-decimals", "BINANCE:BTCUSDT=8", "--price-decimals=2"])).unwrap();
+
+        assert_eq!(app_config.price_precision.for_symbol("BINANCE:BTCUSDT"), 8);
+        assert_eq!(app_config.price_precision.for_symbol("AAPL"), 2);
+
+        assert!(AppConfig::from_args(args(&["--price-decimals", "12"])).is_err());
+        assert!(AppConfig::from_args(args(&["--symbol-decimals", "AAPL"])).is_err());
+    }
+
+    #[test]
+    fn parses_candle_intervals() {
+        let app_config = AppConfig::from_args(args(&["--intervals", "5s,15m,1h,4h,1d", "--symbol-intervals=AAPL=1m;MSFT=1d"])).unwrap();
+
+        assert_eq!(app_config.candle_intervals.for_symbol("TSM"), &[5, 900, 3_600, 14_400, 86_400]);
+        assert_eq!(app_config.candle_intervals.for_symbol("AAPL"), &[60]);
+        assert_eq!(app_config.candle_intervals.for_symbol("MSFT"), &[86_400]);
+        assert_eq!(AppConfig::new().candle_intervals.for_symbol("TSM"), &[10, 60, 300, 600]);
+
+        assert!(AppConfig::from_args(args(&["--intervals", "7m"])).is_err());
+    }
+
+    #[test]
+    fn parses_startup_options() {
+        let app_config = AppConfig::from_args(args(&["--fallback-symbols", "AAPL,MSFT", "--startup-attempts", "1"])).unwrap();
+
+        assert_eq!(app_config.fallback_symbols, Some(args(&["AAPL", "MSFT"])));
+        assert_eq!(app_config.startup_attempts, 1);
+        assert_eq!(AppConfig::new().startup_attempts, 5);
+
+        assert!(AppConfig::from_args(args(&["--startup-attempts", "0"])).is_err());
+    }
+
+    #[test]
+    fn parses_queue_options() {
+        let app_config = AppConfig::from_args(args(&["--queue-size", "500", "--queue-overflow", "coalesce"])).unwrap();
+
+        assert_eq!(app_config.queue_size, 500);
+        assert_eq!(app_config.queue_overflow, OverflowPolicy::Coalesce);
+        assert_eq!(AppConfig::new().queue_overflow, OverflowPolicy::DropOldest);
+
+        assert!(AppConfig::from_args(args(&["--queue-size", "0"])).is_err());
+        assert!(AppConfig::from_args(args(&["--queue-overflow", "drop-newest"])).is_err());
+    }
+
+    #[test]
+    fn parses_data_store_encoding() {
+        let app_config = AppConfig::from_args(args(&["--data-store-encoding", "msgpack"])).unwrap();
+
+        assert_eq!(app_config.data_store_encoding, WireEncoding::MessagePack);
+        assert_eq!(AppConfig::new().data_store_encoding, WireEncoding::Json);
+
+        assert!(AppConfig::from_args(args(&["--data-store-encoding", "cbor"])).is_err());
+    }
+
+    #[test]
+    fn parses_bar_policy() {
+        let app_config = AppConfig::from_args(args(&["--bar-grace-ms", "0", "--late-trades", "amend"])).unwrap();
+
+        assert_eq!(app_config.bar_policy.grace_ms, 0);
+        assert_eq!(app_config.bar_policy.late_trades, LateTradePolicy::Amend);
+
+        assert!(AppConfig::from_args(args(&["--bar-grace-ms", "-5"])).is_err());
+        assert!(AppConfig::from_args(args(&["--late-trades", "keep"])).is_err());
+    }
+
+    #[test]
+    fn parses_backfill() {
+        let app_config = AppConfig::from_args(args(&["--backfill-minutes", "60", "--backfill-url", "http://localhost:8080"])).unwrap();
+
+        assert_eq!(app_config.backfill_minutes, 60);
+        assert_eq!(app_config.backfill_url, Some("http://localhost:8080".to_string()));
+        assert_eq!(AppConfig::new().backfill_minutes, 0);
+
+        assert!(AppConfig::from_args(args(&["--backfill-minutes", "an hour"])).is_err());
+    }
+
+    #[test]
+    fn parses_replay() {
+        let app_config = AppConfig::from_args(args(&[
+            "--provider", "replay",
+            "--replay", "./journal",
+            "--replay-speed", "60x",
+            "--replay-from", "2024-09-06T13:30:00Z",
+            "--replay-to", "2024-09-06T20:00:00Z",
+        ])).unwrap();
+
+        assert!(app_config.is_replay());
+        assert_eq!(app_config.replay_pace, ReplayPace::Accelerated(60.0));
+        assert_eq!(app_config.replay_from, Some(1_725_629_400_000));
+        assert_eq!(app_config.replay_to, Some(1_725_652_800_000));
+
+        assert_eq!(ReplayPace::parse("fast").unwrap(), ReplayPace::AsFastAsPossible);
+        assert_eq!(ReplayPace::parse("1").unwrap(), ReplayPace::Original);
+        assert!(ReplayPace::parse("0").is_err());
+
+        assert!(AppConfig::from_args(args(&["--provider", "replay"])).is_err());
+        assert!(AppConfig::from_args(args(&["--provider", "replay,finnhub", "--replay", "x"])).is_err());
+        assert!(AppConfig::from_args(args(&["--replay-from", "yesterday"])).is_err());
+    }
+
+    #[test]
+    fn tiingo_quotes_is_a_flag() {
+        let app_config = AppConfig::from_args(args(&["--tiingo-quotes", "--provider", "tiingo"])).unwrap();
+
+        assert!(app_config.tiingo_quotes);
+        assert_eq!(app_config.providers, vec![ProviderKind::Tiingo]);
+        assert!(!AppConfig::new().tiingo_quotes);
+    }
+
+    #[test]
+    fn rejects_unknown_input() {
+        assert!(AppConfig::from_args(args(&["--provider", "bloomberg"])).is_err());
+        assert!(AppConfig::from_args(args(&["--verbose", "1"])).is_err());
+        assert!(AppConfig::from_args(args(&["--symbols"])).is_err());
+        assert!(AppConfig::from_args(args(&["--provider", "alpaca,alpaca"])).is_err());
+        assert!(AppConfig::from_args(args(&["--dedup", "last-arrival"])).is_err());
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+
+const SECONDS_PER_DAY: usize = 86_400;
+
+/*
+    Bar lengths in seconds that are built on top of the 1 second bars, per symbol with a
+    default set for everything else. Every interval divides a day so bars can be aligned
+    to wall-clock boundaries.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct CandleIntervals {
+    default_intervals: Vec<usize>,
+    symbol_intervals: HashMap<String, Vec<usize>>,
+}
+
+impl CandleIntervals {
+    pub fn new(default_intervals: Vec<usize>) -> Self {
+        CandleIntervals {
+            default_intervals: normalize(default_intervals),
+            symbol_intervals: HashMap::new(),
+        }
+    }
+
+    pub fn set_default(&mut self, intervals: Vec<usize>) {
+        self.default_intervals = normalize(intervals);
+    }
+
+    pub fn set_symbol(&mut self, symbol: &str, intervals: Vec<usize>) {
+        self.symbol_intervals.insert(symbol.to_string(), normalize(intervals));
+    }
+
+    pub fn for_symbol(&self, symbol: &str) -> &[usize] {
+        match self.symbol_intervals.get(symbol) {
+            Some(v) => v,
+            None => &self.default_intervals,
+        }
+    }
+
+    /*
+        Parses "AAPL=5s,1m,1h;BINANCE:BTCUSDT=15m,4h,1d"
+    */
+    pub fn parse_symbols(&mut self, raw_value: &str) -> Result<(), String> {
+        for entry in raw_value.split(';').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+            let (symbol, intervals) = match entry.rsplit_once('=') {
+                Some(v) => v,
+                None => return Err(format!("Expected SYMBOL=INTERVALS, got: {}", entry)),
+            };
+
+            self.set_symbol(symbol.trim(), parse_intervals(intervals)?);
+        }
+
+        Ok(())
+    }
+}
+
+/*
+    Parses a comma separated list like "5s,15m,1h,4h,1d"
+*/
+pub fn parse_intervals(raw_value: &str) -> Result<Vec<usize>, String> {
+    let mut intervals: Vec<usize> = Vec::new();
+
+    for interval in raw_value.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+        intervals.push(parse_interval(interval)?);
+    }
+
+    match intervals.is_empty() {
+        true => Err("No interval given".to_string()),
+        false => Ok(intervals),
+    }
+}
+
+pub fn parse_interval(raw_value: &str) -> Result<usize, String> {
+    let split_at = raw_value.len() - raw_value.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let (amount, unit) = raw_value.split_at(split_at);
+
+    let multiplier: usize = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3_600,
+        "d" => SECONDS_PER_DAY,
+        _ => return Err(format!("Invalid interval unit, expected s, m, h or d: {}", raw_value)),
+    };
+
+    let seconds = match amount.parse::<usize>() {
+        Ok(v) if v > 0 => v * multiplier,
+        _ => return Err(format!("Invalid interval: {}", raw_value)),
+    };
+
+    match SECONDS_PER_DAY.is_multiple_of(seconds) {
+        true => Ok(seconds),
+        false => Err(format!("Interval has to divide a day: {}", raw_value)),
+    }
+}
+
+/*
+    Sorted, without duplicates and without the 1 second bars which are always sent
+*/
+fn normalize(mut intervals: Vec<usize>) -> Vec<usize> {
+    intervals.retain(|v| *v > 1);
+    intervals.sort_unstable();
+    intervals.dedup();
+
+    intervals
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::values_store::candle_intervals::{CandleIntervals, parse_intervals};
+
+    #[test]
+    fn parses_interval_units() {
+        assert_eq!(parse_intervals("5s, 15m,1h,4h,1d").unwrap(), vec![5, 900, 3_600, 14_400, 86_400]);
+
+        assert!(parse_intervals("").is_err());
+        assert!(parse_intervals("7m").is_err());
+        assert!(parse_intervals("2d").is_err());
+        assert!(parse_intervals("0s").is_err());
+        assert!(parse_intervals("1w").is_err());
+        assert!(parse_intervals("m").is_err());
+    }
+
+    #[test]
+    fn symbol_overrides_default() {
+        let mut candle_intervals = CandleIntervals::new(vec![600, 60, 1, 60]);
+
+        candle_intervals.parse_symbols("AAPL=5s,1d; BINANCE:BTCUSDT=1h").unwrap();
+
+        assert_eq!(candle_intervals.for_symbol("MSFT"), &[60, 600]);
+        assert_eq!(candle_intervals.for_symbol("AAPL"), &[5, 86_400]);
+        assert_eq!(candle_intervals.for_symbol("BINANCE:BTCUSDT"), &[3_600]);
+
+        assert!(candle_intervals.parse_symbols("AAPL").is_err());
+        assert!(candle_intervals.parse_symbols("AAPL=13s").is_err());
+    }
+}
@@ -4,15 +4,16 @@ use std:: {
 
 use crate::file_reader::credentials_reader::CredentialsReader;
 
+#[derive(Clone)]
 pub struct CredentialsStore {
     credentials_map: HashMap<String, String>,
 }
 
 impl CredentialsStore {
-    pub fn new() -> Self {
+    pub fn new(credentials_path: &str) -> Self {
         let mut credentials_store = CredentialsStore{ credentials_map:HashMap::new() };
 
-        let credentials_reader:CredentialsReader = CredentialsReader::new("./credentials/apikeys.xml".to_string());
+        let credentials_reader:CredentialsReader = CredentialsReader::new(credentials_path.to_string());
         credentials_store.insert_values_into_map(&credentials_reader.get_credentials());
 
         credentials_store
@@ -1 +1,4 @@
-pub mod credentials_store;
\ No newline at end of file
+pub mod credentials_store;
+pub mod app_config;
+pub mod price_precision;
+pub mod candle_intervals;
\ No newline at end of file
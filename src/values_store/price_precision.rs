@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+
+use crate::data_analysis::price::MAX_DECIMALS;
+
+/*
+    Number of decimals prices are rounded to, per symbol with a default for everything else
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct PricePrecision {
+    default_decimals: u32,
+    symbol_decimals: HashMap<String, u32>,
+}
+
+impl PricePrecision {
+    pub fn new(default_decimals: u32) -> Self {
+        PricePrecision {
+            default_decimals: default_decimals.min(MAX_DECIMALS),
+            symbol_decimals: HashMap::new(),
+        }
+    }
+
+    pub fn set_default(&mut self, decimals: u32) {
+        self.default_decimals = decimals.min(MAX_DECIMALS);
+    }
+
+    pub fn set_symbol(&mut self, symbol: &str, decimals: u32) {
+        self.symbol_decimals.insert(symbol.to_string(), decimals.min(MAX_DECIMALS));
+    }
+
+    pub fn for_symbol(&self, symbol: &str) -> u32 {
+        match self.symbol_decimals.get(symbol) {
+            Some(v) => *v,
+            None => self.default_decimals,
+        }
+    }
+
+    /*
+        Parses "BINANCE:BTCUSDT=8,OANDA:EUR_USD=5"
+    */
+    pub fn parse_symbols(&mut self, raw_value: &str) -> Result<(), String> {
+        for entry in raw_value.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+            let (symbol, decimals) = match entry.rsplit_once('=') {
+                Some(v) => v,
+                None => return Err(format!("Expected SYMBOL=DECIMALS, got: {}", entry)),
+            };
+
+            self.set_symbol(symbol, parse_decimals(decimals)?);
+        }
+
+        Ok(())
+    }
+}
+
+pub fn parse_decimals(raw_value: &str) -> Result<u32, String> {
+    match raw_value.parse::<u32>() {
+        Ok(v) if v <= MAX_DECIMALS => Ok(v),
+        _ => Err(format!("Invalid number of decimals (0-{}): {}", MAX_DECIMALS, raw_value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::values_store::price_precision::PricePrecision;
+
+    #[test]
+    fn symbol_overrides_default() {
+        let mut price_precision = PricePrecision::new(4);
+
+        price_precision.parse_symbols("BINANCE:BTCUSDT=8, AAPL=2").unwrap();
+
+        assert_eq!(price_precision.for_symbol("BINANCE:BTCUSDT"), 8);
+        assert_eq!(price_precision.for_symbol("AAPL"), 2);
+        assert_eq!(price_precision.for_symbol("MSFT"), 4);
+
+        assert!(price_precision.parse_symbols("AAPL").is_err());
+        assert!(price_precision.parse_symbols("AAPL=9").is_err());
+    }
+}
@@ -0,0 +1,338 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{SecondsFormat, TimeZone, Utc};
+use ureq::{Agent, AgentBuilder, Request};
+
+use crate::values_store::app_config::ProviderKind;
+use crate::values_store::credentials_store::CredentialsStore;
+use crate::data_analysis::stock_analysis::StockAnalyserWeb;
+use crate::data_analysis::historical_bar::HistoricalBar;
+use crate::data_parsers::finnhub_history_parser::parse_finnhub_candles;
+use crate::data_parsers::alpaca_history_parser::parse_alpaca_bars;
+use crate::data_parsers::parse_error::ParseError;
+
+const BAR_SECONDS: usize = 60;
+const MAX_PAGES: usize = 100;
+
+/*
+    REST endpoint of a vendor that serves 1 minute bars of the recent past. Vendors that page
+    their answers return the token of the next page with the bars, it is passed to the
+    request of that page.
+*/
+pub trait HistoryProvider {
+    fn name(&self) -> &str;
+
+    fn request(&self, agent: &Agent, symbol: &str, from_ms: i64, to_ms: i64, page_token: Option<&str>) -> Request;
+
+    fn parse_bars(&self, symbol: &str, body: &str) -> Result<(Vec<HistoricalBar>, Option<String>), ParseError>;
+}
+
+pub struct FinnhubHistory {
+    base_url: String,
+    token: String,
+}
+
+impl FinnhubHistory {
+    pub fn new(base_url: &str, token: &str) -> Self {
+        FinnhubHistory { base_url: base_url.trim_end_matches('/').to_string(), token: token.to_string() }
+    }
+}
+
+impl HistoryProvider for FinnhubHistory {
+    fn name(&self) -> &str {
+        "Finnhub"
+    }
+
+    fn request(&self, agent: &Agent, symbol: &str, from_ms: i64, to_ms: i64, _page_token: Option<&str>) -> Request {
+        agent.get(&format!("{}/stock/candle", self.base_url))
+            .query("symbol", symbol)
+            .query("resolution", "1")
+            .query("from", &(from_ms / 1000).to_string())
+            .query("to", &(to_ms / 1000).to_string())
+            .query("token", &self.token)
+    }
+
+    fn parse_bars(&self, symbol: &str, body: &str) -> Result<(Vec<HistoricalBar>, Option<String>), ParseError> {
+        Ok((parse_finnhub_candles(symbol, BAR_SECONDS, body)?, None))
+    }
+}
+
+pub struct AlpacaHistory {
+    base_url: String,
+    key: String,
+    secret: String,
+}
+
+impl AlpacaHistory {
+    pub fn new(base_url: &str, key: &str, secret: &str) -> Self {
+        AlpacaHistory { base_url: base_url.trim_end_matches('/').to_string(), key: key.to_string(), secret: secret.to_string() }
+    }
+}
+
+impl HistoryProvider for AlpacaHistory {
+    fn name(&self) -> &str {
+        "Alpaca"
+    }
+
+    fn request(&self, agent: &Agent, symbol: &str, from_ms: i64, to_ms: i64, page_token: Option<&str>) -> Request {
+        let request = agent.get(&format!("{}/v2/stocks/{}/bars", self.base_url, symbol))
+            .query("timeframe", "1Min")
+            .query("start", &rfc3339(from_ms))
+            .query("end", &rfc3339(to_ms))
+            .query("limit", "10000")
+            .set("APCA-API-KEY-ID", &self.key)
+            .set("APCA-API-SECRET-KEY", &self.secret);
+
+        match page_token {
+            Some(v) => request.query("page_token", v),
+            None => request,
+        }
+    }
+
+    fn parse_bars(&self, symbol: &str, body: &str) -> Result<(Vec<HistoricalBar>, Option<String>), ParseError> {
+        parse_alpaca_bars(symbol, BAR_SECONDS, body)
+    }
+}
+
+/*
+    History endpoint of the provider, base_url replaces the vendor's address.
+    None for vendors without one.
+*/
+pub fn history_provider(provider: ProviderKind, credentials_store: &CredentialsStore, base_url: Option<&str>) -> Option<Box<dyn HistoryProvider>> {
+    match provider {
+        ProviderKind::Finnhub => Some(Box::new(FinnhubHistory::new(
+            base_url.unwrap_or("https://finnhub.io/api/v1"),
+            &credentials_store.get_token("Finnhub.io"),
+        ))),
+        ProviderKind::Alpaca => Some(Box::new(AlpacaHistory::new(
+            base_url.unwrap_or("https://data.alpaca.markets"),
+            &credentials_store.get_token("alpaca.markets.key"),
+            &credentials_store.get_token("alpaca.markets.secret"),
+        ))),
+        _ => None,
+    }
+}
+
+/*
+    Pulls the bars of the last lookback_ms for every symbol before the live stream starts.
+    Only complete minutes are requested, the current one is left to the live trades.
+*/
+pub struct Backfill {
+    agent: Agent,
+    lookback_ms: i64,
+}
+
+impl Backfill {
+    pub fn new(lookback_ms: i64) -> Self {
+        let mut agent_builder = AgentBuilder::new().timeout(Duration::from_secs(10));
+
+        if let Ok(v) = native_tls::TlsConnector::new() {
+            agent_builder = agent_builder.tls_connector(Arc::new(v));
+        }
+
+        Backfill { agent: agent_builder.build(), lookback_ms }
+    }
+
+    pub fn run(&self, history_provider: &dyn HistoryProvider, stock_analysis_web: &mut StockAnalyserWeb, list_of_stocks: &[String], now_ms: i64) -> usize {
+        let to_ms = now_ms - now_ms.rem_euclid(BAR_SECONDS as i64 * 1000);
+        let from_ms = to_ms - self.lookback_ms;
+        let mut num_of_bars: usize = 0;
+
+        for stock in list_of_stocks.iter() {
+            let list_of_bars = match self.fetch(history_provider, stock, from_ms, to_ms) {
+                Ok(v) => v,
+                Err(e) => {
+                    println!("Error backfilling {} from {}: {}", stock, history_provider.name(), e);
+                    continue;
+                },
+            };
+
+            let mut list_of_bars: Vec<HistoricalBar> = list_of_bars.into_iter()
+                .filter(|v| v.timestamp >= from_ms && v.end_millis() <= to_ms)
+                .collect();
+            list_of_bars.sort_by_key(|v| v.timestamp);
+
+            println!("Backfilled {} bars of {} from {}", list_of_bars.len(), stock, history_provider.name());
+
+            num_of_bars += list_of_bars.len();
+            stock_analysis_web.add_history(list_of_bars);
+        }
+
+        num_of_bars
+    }
+
+    /*
+        Follows the pages of the answer, at most MAX_PAGES of them
+    */
+    fn fetch(&self, history_provider: &dyn HistoryProvider, symbol: &str, from_ms: i64, to_ms: i64) -> Result<Vec<HistoricalBar>, String> {
+        let mut list_of_bars: Vec<HistoricalBar> = Vec::new();
+        let mut page_token: Option<String> = None;
+
+        for _ in 0..MAX_PAGES {
+            let body = match history_provider.request(&self.agent, symbol, from_ms, to_ms, page_token.as_deref()).call() {
+                Ok(v) => v.into_string().map_err(|e| e.to_string())?,
+                Err(ureq::Error::Status(code, response)) => {
+                    return Err(format!("HTTP {} {}", code, response.into_string().unwrap_or_default()));
+                },
+                Err(e) => return Err(e.to_string()),
+            };
+
+            let (mut list_of_page_bars, next_page_token) = history_provider.parse_bars(symbol, &body).map_err(|e| e.to_string())?;
+            list_of_bars.append(&mut list_of_page_bars);
+
+            page_token = match next_page_token {
+                Some(v) if !v.is_empty() => Some(v),
+                _ => return Ok(list_of_bars),
+            };
+        }
+
+        println!("Stopped backfilling {} from {} after {} pages", symbol, history_provider.name(), MAX_PAGES);
+
+        Ok(list_of_bars)
+    }
+}
+
+fn rfc3339(timestamp_ms: i64) -> String {
+    match Utc.timestamp_millis_opt(timestamp_ms).single() {
+        Some(v) => v.to_rfc3339_opts(SecondsFormat::Secs, true),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+
+    use crate::values_store::app_config::{BarPolicy, DedupRule, LateTradePolicy, OverflowPolicy, ProviderKind};
+    use crate::values_store::price_precision::PricePrecision;
+    use crate::values_store::candle_intervals::CandleIntervals;
+    use crate::database_clients::data_web_client::DataWebClient;
+    use crate::database_clients::trade_web_server::TradeWebServer;
+    use crate::data_analysis::stock_analysis::StockAnalyserWeb;
+    use crate::data_analysis::trade_consolidator::TradeConsolidator;
+    use crate::data_analysis::clock::ManualClock;
+    use crate::data_parsers::fixtures::fixture;
+    use crate::web_clients::backfill::{Backfill, FinnhubHistory, AlpacaHistory};
+
+    // 2024-09-06 15:28:30 UTC
+    const NOW: i64 = 1_725_636_510_000;
+
+    /*
+        Answers one request per body in order and hands the request heads to the test
+    */
+    fn serve(status: &str, list_of_bodies: Vec<String>) -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = format!("http://{}", listener.local_addr().unwrap());
+        let status = status.to_string();
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            for body in list_of_bodies.into_iter() {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut head = String::new();
+
+                loop {
+                    let mut line = String::new();
+
+                    if reader.read_line(&mut line).unwrap() == 0 || line == "\r\n" {
+                        break;
+                    }
+
+                    head.push_str(&line);
+                }
+
+                let _ = write!(stream, "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body);
+                let _ = sender.send(head);
+            }
+        });
+
+        (addr, receiver)
+    }
+
+    #[test]
+    fn seeds_complete_minutes_of_every_symbol() {
+        // the bar of 15:28 is still open at NOW and is left to the live trades
+        let body = concat!(
+            r#"{"c":[222.3,222.1,223],"h":[222.5,222.4,223],"l":[222,221.95,223],"o":[222.25,222.3,223],"#,
+            r#""s":"ok","t":[1725636360,1725636420,1725636480],"v":[1200,800,500]}"#,
+        );
+        let (addr, receiver) = serve("200 OK", vec![body.to_string(), fixture("finnhub/history/no_data.json")]);
+
+        let mut stock_analysis_web = StockAnalyserWeb::new(
+            DataWebClient::new("ws://127.0.0.1:9", 1_000, OverflowPolicy::DropOldest),
+            TradeWebServer::new("127.0.0.1:9"),
+            TradeConsolidator::new(DedupRule::FirstArrival, ProviderKind::Finnhub),
+            PricePrecision::new(2),
+            CandleIntervals::new(vec![60]),
+            BarPolicy { grace_ms: 500, late_trades: LateTradePolicy::Drop },
+        );
+        stock_analysis_web.set_clock(Arc::new(ManualClock::new(NOW)));
+
+        let backfill = Backfill::new(3_600_000);
+        let num_of_bars = backfill.run(&FinnhubHistory::new(&addr, "secret"), &mut stock_analysis_web, &["AAPL".to_string(), "MSFT".to_string()], NOW);
+
+        assert_eq!(num_of_bars, 2);
+        assert!(receiver.recv().unwrap().contains("symbol=AAPL&resolution=1&from=1725632880&to=1725636480&"));
+        assert!(receiver.recv().unwrap().contains("symbol=MSFT&resolution=1&from=1725632880&to=1725636480&"));
+
+        let list_of_states = stock_analysis_web.symbol_states(None);
+        let today = list_of_states[0].today.as_ref().unwrap();
+
+        assert_eq!(list_of_states.len(), 1);
+        assert_eq!((today.open.as_str(), today.high.as_str(), today.low.as_str(), today.close.as_str()), ("222.25", "222.50", "221.95", "222.10"));
+        assert_eq!(today.volume, 2_000);
+    }
+
+    #[test]
+    fn sends_finnhub_range_and_token() {
+        let (addr, receiver) = serve("200 OK", vec![fixture("finnhub/history/candles.json")]);
+        let backfill = Backfill::new(3_600_000);
+
+        let list_of_bars = backfill.fetch(&FinnhubHistory::new(&addr, "secret"), "AAPL", NOW - 3_600_000, NOW).unwrap();
+        let head = receiver.recv().unwrap();
+
+        assert_eq!(list_of_bars.len(), 2);
+        assert!(head.starts_with("GET /stock/candle?symbol=AAPL&resolution=1&from=1725632910&to=1725636510&token=secret "), "{}", head);
+    }
+
+    #[test]
+    fn sends_alpaca_credentials() {
+        let (addr, receiver) = serve("200 OK", vec![fixture("alpaca/history/bars.json")]);
+        let backfill = Backfill::new(3_600_000);
+
+        let list_of_bars = backfill.fetch(&AlpacaHistory::new(&addr, "key", "secret"), "AAPL", NOW - 120_000, NOW).unwrap();
+        let head = receiver.recv().unwrap().to_lowercase();
+
+        assert_eq!(list_of_bars[1].close_price.to_string(), "222.1");
+        assert!(head.starts_with("get /v2/stocks/aapl/bars?timeframe=1min&start=2024-09-06t15%3a26%3a30z"), "{}", head);
+        assert!(head.contains("apca-api-key-id: key"));
+        assert!(head.contains("apca-api-secret-key: secret"));
+    }
+
+    #[test]
+    fn follows_alpaca_pages() {
+        let (addr, receiver) = serve("200 OK", vec![fixture("alpaca/history/first_page.json"), fixture("alpaca/history/bars.json")]);
+        let backfill = Backfill::new(3_600_000);
+
+        let list_of_bars = backfill.fetch(&AlpacaHistory::new(&addr, "key", "secret"), "AAPL", NOW - 240_000, NOW).unwrap();
+
+        assert_eq!(list_of_bars.iter().map(|v| v.timestamp).collect::<Vec<i64>>(), vec![1_725_636_300_000, 1_725_636_360_000, 1_725_636_420_000]);
+        assert!(!receiver.recv().unwrap().contains("page_token"));
+        assert!(receiver.recv().unwrap().contains("&page_token=QUFQTHxNfDE3MjU2MzYzMDAwMDA%3D "));
+    }
+
+    #[test]
+    fn reports_http_errors() {
+        let (addr, _receiver) = serve("403 Forbidden", vec![fixture("alpaca/history/forbidden.json")]);
+        let backfill = Backfill::new(3_600_000);
+
+        let error = backfill.fetch(&AlpacaHistory::new(&addr, "key", "secret"), "AAPL", NOW - 120_000, NOW).unwrap_err();
+
+        assert!(error.starts_with("HTTP 403"), "{}", error);
+    }
+}
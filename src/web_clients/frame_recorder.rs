@@ -0,0 +1,193 @@
+use std::{
+    thread,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, atomic::{AtomicU64, Ordering}},
+    sync::mpsc::{self, SyncSender, Receiver, RecvTimeoutError, TrySendError},
+    time::{Duration, Instant},
+};
+
+use flate2::{Compression, write::GzEncoder};
+
+use crate::values_store::app_config::ProviderKind;
+use crate::data_analysis::clock::now_millis;
+use crate::data_analysis::trade::Trade;
+
+const QUEUE_SIZE: usize = 100_000;
+
+/*
+    Captures every text frame of a provider to <dir>/<provider>-<YYYY-MM-DD>.frames.gz,
+    one "receive_ms \t provider \t frame" line per frame, the format read by the replay.
+
+    The read loop only hands the frame to a channel. A writer thread compresses the frames
+    of each second into its own gzip member, so a crash loses at most the last second and
+    the file stays readable. Frames are dropped and counted if the writer falls behind.
+*/
+#[derive(Clone)]
+pub struct FrameRecorder {
+    provider: ProviderKind,
+    sender: SyncSender<(i64, String)>,
+    dropped_frames: Arc<AtomicU64>,
+}
+
+impl FrameRecorder {
+    pub fn start(dir: &str, provider: ProviderKind) -> Self {
+        let (sender, receiver) = mpsc::sync_channel::<(i64, String)>(QUEUE_SIZE);
+        let dir = PathBuf::from(dir);
+
+        thread::spawn(move || {
+            write_frames(&dir, provider, receiver);
+        });
+
+        FrameRecorder { provider, sender, dropped_frames: Arc::new(AtomicU64::new(0)) }
+    }
+
+    pub fn record(&self, frame: &str) {
+        match self.sender.try_send((now_millis(), frame.to_string())) {
+            Ok(()) => (),
+            Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => {
+                if self.dropped_frames.fetch_add(1, Ordering::Relaxed).is_multiple_of(1000) {
+                    println!("Recorder of {} is behind, frames are dropped", self.provider);
+                }
+            },
+        };
+    }
+}
+
+fn write_frames(dir: &Path, provider: ProviderKind, receiver: Receiver<(i64, String)>) {
+    let mut batch: Vec<(i64, String)> = Vec::new();
+    let mut last_write = Instant::now();
+
+    loop {
+        let disconnected = match receiver.recv_timeout(Duration::from_millis(200)) {
+            Ok(v) => { batch.push(v); false },
+            Err(RecvTimeoutError::Timeout) => false,
+            Err(RecvTimeoutError::Disconnected) => true,
+        };
+
+        if disconnected || last_write.elapsed() >= Duration::from_millis(1000) {
+            if let Err(e) = write_batch(dir, provider, &batch) {
+                println!("Error recording {} frames: {}", provider, e);
+            }
+
+            batch.clear();
+            last_write = Instant::now();
+        }
+
+        if disconnected {
+            return;
+        }
+    }
+}
+
+/*
+    Frames are split into files by the day they were received on
+*/
+fn write_batch(dir: &Path, provider: ProviderKind, batch: &[(i64, String)]) -> io::Result<()> {
+    let mut start = 0;
+
+    while start < batch.len() {
+        let day = capture_day(batch[start].0);
+        let end = start + batch[start..].iter().take_while(|(v, _)| capture_day(*v) == day).count();
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+
+        for (receive_ms, frame) in batch[start..end].iter() {
+            writeln!(encoder, "{}", frame_to_line(*receive_ms, provider, frame))?;
+        }
+
+        fs::create_dir_all(dir)?;
+
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(dir.join(format!("{}-{}.frames.gz", provider, day)))?;
+        file.write_all(&encoder.finish()?)?;
+        file.sync_data()?;
+
+        start = end;
+    }
+
+    Ok(())
+}
+
+fn capture_day(receive_ms: i64) -> String {
+    match Trade::timestamp_from_millis(receive_ms) {
+        Some(v) => v.format("%Y-%m-%d").to_string(),
+        None => "unknown".to_string(),
+    }
+}
+
+pub fn frame_to_line(receive_ms: i64, provider: ProviderKind, frame: &str) -> String {
+    format!("{}\t{}\t{}", receive_ms, provider, frame.replace(['\n', '\r'], " "))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::values_store::app_config::ProviderKind;
+    use crate::data_analysis::clock::now_millis;
+    use crate::web_clients::frame_recorder::{FrameRecorder, write_batch, capture_day};
+    use crate::web_clients::replay::{ReplayItem, read_frames};
+
+    fn capture_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("stockwatch-frames-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        dir
+    }
+
+    fn frames(path: &Path) -> Vec<(i64, ProviderKind, String)> {
+        read_frames(path.to_str().unwrap()).unwrap().into_iter().map(|v| match v.item {
+            ReplayItem::Frame(provider, frame) => (v.timestamp, provider, frame),
+            ReplayItem::Trade(_) => panic!("Expected a frame"),
+        }).collect()
+    }
+
+    #[test]
+    fn batches_are_appended_as_gzip_members() {
+        let dir = capture_dir("members");
+
+        write_batch(&dir, ProviderKind::Finnhub, &[(1_725_636_476_438, "{\"type\":\"ping\"}".to_string())]).unwrap();
+        write_batch(&dir, ProviderKind::Finnhub, &[
+            (1_725_636_477_000, "{\"type\":\"trade\",\n\"data\":[]}".to_string()),
+            (1_725_667_200_000, "{\"type\":\"ping\"}".to_string()),
+        ]).unwrap();
+
+        assert_eq!(frames(&dir.join("finnhub-2024-09-06.frames.gz")), vec![
+            (1_725_636_476_438, ProviderKind::Finnhub, "{\"type\":\"ping\"}".to_string()),
+            (1_725_636_477_000, ProviderKind::Finnhub, "{\"type\":\"trade\", \"data\":[]}".to_string()),
+        ]);
+        assert_eq!(frames(&dir.join("finnhub-2024-09-07.frames.gz")).len(), 1);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn recorder_writes_in_the_background() {
+        let dir = capture_dir("background");
+        let frame_recorder = FrameRecorder::start(dir.to_str().unwrap(), ProviderKind::Alpaca);
+
+        frame_recorder.record("[{\"T\":\"success\",\"msg\":\"connected\"}]");
+        drop(frame_recorder);
+
+        let path = dir.join(format!("alpaca-{}.frames.gz", capture_day(now_millis())));
+
+        for _ in 0..100 {
+            if read_frames(path.to_str().unwrap()).is_ok_and(|v| !v.is_empty()) {
+                break;
+            }
+
+            thread::sleep(Duration::from_millis(20));
+        }
+
+        assert_eq!(frames(&path)[0].2, "[{\"T\":\"success\",\"msg\":\"connected\"}]");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
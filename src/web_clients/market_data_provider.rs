@@ -0,0 +1,42 @@
+use std::net::TcpStream;
+use std::time::Duration;
+
+use tungstenite::{
+    Message,
+    WebSocket,
+    Error,
+    stream::MaybeTlsStream
+};
+
+pub type WsClient = WebSocket<MaybeTlsStream<TcpStream>>;
+
+/*
+    Vendor specific parts of a market data feed. The connect/read/reconnect loop
+    lives in SessionDriver, a provider only describes what is different.
+*/
+pub trait MarketDataProvider {
+    fn name(&self) -> &str;
+
+    fn url(&self) -> String;
+
+    /*
+        Called right after the websocket handshake, before subscribing
+    */
+    fn on_connect(&mut self, _client: &mut WsClient) -> Result<(), Box<Error>> {
+        Ok(())
+    }
+
+    fn subscribe(&mut self, client: &mut WsClient, list_of_stocks: &[String]) -> Result<(), Box<Error>>;
+
+    /*
+        Handles one text frame. Returns false if the frame didn't contain usable data
+    */
+    fn parse_frame(&mut self, text: &str) -> bool;
+
+    /*
+        Message and interval of an application level heartbeat, if the vendor needs one
+    */
+    fn heartbeat(&self) -> Option<(Duration, Message)> {
+        None
+    }
+}
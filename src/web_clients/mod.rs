@@ -2,4 +2,9 @@ pub mod eodhd;
 pub mod alpaca;
 pub mod finnhub;
 pub mod twelve;
-pub mod tiingo;
\ No newline at end of file
+pub mod tiingo;
+pub mod market_data_provider;
+pub mod session_driver;
+pub mod backfill;
+pub mod replay;
+pub mod frame_recorder;
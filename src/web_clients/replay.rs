@@ -0,0 +1,249 @@
+use std::{
+    thread,
+    fs::{self, File},
+    io::{self, BufRead, BufReader},
+    path::Path,
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::values_store::app_config::{ProviderKind, ReplayPace};
+use crate::data_analysis::stock_analysis::StockAnalyserWeb;
+use crate::data_analysis::trade::Trade;
+use crate::data_analysis::clock::ManualClock;
+use crate::data_parsers::tiingo_parser::parse_tiingo_data;
+
+use flate2::read::MultiGzDecoder;
+
+use crate::database_clients::trade_journal::{read_range, list_groups, journal_bounds};
+
+pub enum ReplayItem {
+    Frame(ProviderKind, String),
+    Trade(Trade),
+}
+
+/*
+    timestamp is the receive time of a frame or the exchange time of a journaled trade
+*/
+pub struct ReplayEvent {
+    pub timestamp: i64,
+    pub item: ReplayItem,
+}
+
+/*
+    Feeds a recorded tape into StockAnalyserWeb in place of a live provider. The candle
+    clock follows the tape, so the bars come out the same at every pace.
+*/
+pub struct ReplayClient {
+    stock_analysis_web: StockAnalyserWeb,
+    pace: ReplayPace,
+    clock: ManualClock,
+    last_twelve_data: HashMap<String, i64>,
+    tiingo_quotes: bool,
+}
+
+impl ReplayClient {
+    pub fn new(mut stock_analysis_web: StockAnalyserWeb, pace: ReplayPace) -> Self {
+        let clock = ManualClock::new(0);
+
+        stock_analysis_web.set_clock(Arc::new(clock.clone()));
+
+        ReplayClient { stock_analysis_web, pace, clock, last_twelve_data: HashMap::new(), tiingo_quotes: false }
+    }
+
+    pub fn set_tiingo_quotes(&mut self, tiingo_quotes: bool) {
+        self.tiingo_quotes = tiingo_quotes;
+    }
+
+    /*
+        Replays the events in order of their timestamps, the clock is moved on by one minute
+        after the last event so its bars are sent as well
+    */
+    pub fn run(&mut self, mut list_of_events: Vec<ReplayEvent>) -> usize {
+        list_of_events.sort_by_key(|v| v.timestamp);
+
+        let first_timestamp = match list_of_events.first() {
+            Some(v) => v.timestamp,
+            None => return 0,
+        };
+
+        let start_time = Instant::now();
+        let mut num_of_events: usize = 0;
+
+        for event in list_of_events.into_iter() {
+            if let Some(v) = pace_delay(self.pace, first_timestamp, event.timestamp) {
+                if let Some(v) = v.checked_sub(start_time.elapsed()) {
+                    thread::sleep(v);
+                }
+            }
+
+            self.clock.set(event.timestamp);
+            self.stock_analysis_web.send_due_candles();
+
+            if self.add_event(event.item) {
+                num_of_events += 1;
+            }
+        }
+
+        self.clock.advance(61_000);
+        self.stock_analysis_web.send_due_candles();
+
+        num_of_events
+    }
+
+    fn add_event(&mut self, item: ReplayItem) -> bool {
+        match item {
+            ReplayItem::Trade(trade) => {
+                self.stock_analysis_web.add_trades(vec![trade]);
+                true
+            },
+            ReplayItem::Frame(provider, frame) => match provider {
+                ProviderKind::Finnhub => self.stock_analysis_web.add_finnhub_data(&frame),
+                ProviderKind::Eodhd => self.stock_analysis_web.add_eodhd_data(&frame),
+                ProviderKind::Alpaca => self.stock_analysis_web.add_alpaca_data(&frame),
+                ProviderKind::Twelve => self.stock_analysis_web.add_twelve_data(&frame, &mut self.last_twelve_data),
+                ProviderKind::Tiingo => self.stock_analysis_web.add_tiingo_update(parse_tiingo_data(&frame, self.tiingo_quotes)),
+                ProviderKind::Replay => false,
+            },
+        }
+    }
+}
+
+/*
+    Time after the start of the replay at which an event is due, None to not wait at all
+*/
+fn pace_delay(pace: ReplayPace, first_timestamp: i64, timestamp: i64) -> Option<Duration> {
+    let offset_ms = (timestamp - first_timestamp).max(0) as f64;
+
+    match pace {
+        ReplayPace::Original => Some(Duration::from_millis(offset_ms as u64)),
+        ReplayPace::Accelerated(factor) => Some(Duration::from_millis((offset_ms / factor) as u64)),
+        ReplayPace::AsFastAsPossible => None,
+    }
+}
+
+/*
+    A file is read as frame recording, a directory with .frames.gz captures as all of
+    them and any other directory as trade journal
+*/
+pub fn read_replay(path: &str, from_ms: Option<i64>, to_ms: Option<i64>) -> io::Result<Vec<ReplayEvent>> {
+    if !Path::new(path).is_dir() {
+        return read_frames(path);
+    }
+
+    let mut list_of_captures: Vec<String> = Vec::new();
+
+    for entry in fs::read_dir(path)? {
+        let capture_path = entry?.path().display().to_string();
+
+        if capture_path.ends_with(".frames.gz") {
+            list_of_captures.push(capture_path);
+        }
+    }
+
+    if list_of_captures.is_empty() {
+        return read_journal(path, from_ms, to_ms);
+    }
+
+    let mut list_of_events: Vec<ReplayEvent> = Vec::new();
+
+    for capture_path in list_of_captures.iter() {
+        list_of_events.append(&mut read_frames(capture_path)?);
+    }
+
+    list_of_events.retain(|v| from_ms.is_none_or(|from| v.timestamp >= from) && to_ms.is_none_or(|to| v.timestamp < to));
+
+    Ok(list_of_events)
+}
+
+/*
+    One frame per line: receive_ms \t provider \t frame, gzip compressed if the name ends
+    with .gz. A capture cut off by a crash is read up to the damaged part.
+*/
+pub fn read_frames(path: &str) -> io::Result<Vec<ReplayEvent>> {
+    let file = File::open(path)?;
+    let reader: Box<dyn BufRead> = match path.ends_with(".gz") {
+        true => Box::new(BufReader::new(MultiGzDecoder::new(file))),
+        false => Box::new(BufReader::new(file)),
+    };
+
+    let mut list_of_events: Vec<ReplayEvent> = Vec::new();
+
+    for line in reader.lines() {
+        let line = match line {
+            Ok(v) => v,
+            Err(e) => {
+                println!("Stopping at unreadable data in {}: {}", path, e);
+                break;
+            },
+        };
+
+        match frame_from_line(&line) {
+            Some(v) => list_of_events.push(v),
+            None => println!("Skipping malformed line in {}", path),
+        };
+    }
+
+    Ok(list_of_events)
+}
+
+fn frame_from_line(line: &str) -> Option<ReplayEvent> {
+    let mut fields = line.splitn(3, '\t');
+
+    let timestamp = fields.next()?.parse::<i64>().ok()?;
+    let provider = ProviderKind::parse(fields.next()?).ok()?;
+    let frame = fields.next()?.to_string();
+
+    Some(ReplayEvent { timestamp, item: ReplayItem::Frame(provider, frame) })
+}
+
+pub fn read_journal(dir: &str, from_ms: Option<i64>, to_ms: Option<i64>) -> io::Result<Vec<ReplayEvent>> {
+    let (first_ms, last_ms) = match journal_bounds(dir)? {
+        Some(v) => v,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut list_of_events: Vec<ReplayEvent> = Vec::new();
+
+    for group in list_groups(dir)?.iter() {
+        for trade in read_range(dir, group, from_ms.unwrap_or(first_ms), to_ms.unwrap_or(last_ms))?.into_iter() {
+            list_of_events.push(ReplayEvent { timestamp: trade.timestamp_millis(), item: ReplayItem::Trade(trade) });
+        }
+    }
+
+    Ok(list_of_events)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::values_store::app_config::{ProviderKind, ReplayPace};
+    use crate::web_clients::replay::{ReplayItem, frame_from_line, pace_delay};
+
+    #[test]
+    fn parses_frame_lines() {
+        let event = frame_from_line("1725636476500\talpaca\t[{\"T\":\"success\",\t\"msg\":\"connected\"}]").unwrap();
+
+        assert_eq!(event.timestamp, 1_725_636_476_500);
+
+        match event.item {
+            ReplayItem::Frame(provider, frame) => {
+                assert_eq!(provider, ProviderKind::Alpaca);
+                assert_eq!(frame, "[{\"T\":\"success\",\t\"msg\":\"connected\"}]");
+            },
+            ReplayItem::Trade(_) => panic!("Expected a frame"),
+        }
+
+        assert!(frame_from_line("1725636476500\tbloomberg\t{}").is_none());
+        assert!(frame_from_line("yesterday\tfinnhub\t{}").is_none());
+    }
+
+    #[test]
+    fn pace_scales_the_gaps() {
+        assert_eq!(pace_delay(ReplayPace::Original, 1_000, 61_000), Some(Duration::from_secs(60)));
+        assert_eq!(pace_delay(ReplayPace::Accelerated(60.0), 1_000, 61_000), Some(Duration::from_secs(1)));
+        assert_eq!(pace_delay(ReplayPace::AsFastAsPossible, 1_000, 61_000), None);
+    }
+}
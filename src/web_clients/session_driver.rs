@@ -0,0 +1,262 @@
+use std::thread;
+use std::io::ErrorKind;
+use std::time::{Duration, Instant};
+
+use tungstenite::{
+    connect,
+    Message,
+    Error,
+    stream::MaybeTlsStream
+};
+
+use crate::web_clients::market_data_provider::{MarketDataProvider, WsClient};
+use crate::web_clients::frame_recorder::FrameRecorder;
+
+/*
+    Owns the connect/subscribe/read loop shared by all vendors. Reconnects on every
+    error and answers Ping, Close and unexpected frames the same way for every provider.
+*/
+pub struct SessionDriver {
+    retry_delay: Duration,
+    reconnect_delay: Duration,
+    read_timeout: Duration,
+    frame_recorder: Option<FrameRecorder>,
+}
+
+impl SessionDriver {
+    pub fn new() -> Self {
+        SessionDriver {
+            retry_delay: Duration::from_millis(20_000),
+            reconnect_delay: Duration::from_millis(1000),
+            read_timeout: Duration::from_millis(50),
+            frame_recorder: None,
+        }
+    }
+
+    pub fn set_frame_recorder(&mut self, frame_recorder: FrameRecorder) {
+        self.frame_recorder = Some(frame_recorder);
+    }
+
+    pub fn run<P: MarketDataProvider>(&self, provider: &mut P, list_of_stocks: &[String]) {
+        loop {
+            match self.run_session(provider, list_of_stocks) {
+                Ok(()) => thread::sleep(self.reconnect_delay),
+                Err(e) => {
+                    println!("Error in {} session: {}", provider.name(), e);
+                    thread::sleep(self.retry_delay);
+                },
+            }
+        }
+    }
+
+    /*
+        Runs a single connection until the server closes it or the connection breaks.
+        Errors before the subscription is complete are returned, errors afterwards end
+        the session like a Close frame does.
+    */
+    pub fn run_session<P: MarketDataProvider>(&self, provider: &mut P, list_of_stocks: &[String]) -> Result<(), Box<Error>> {
+        let (mut client, _response) = connect(provider.url())?;
+
+        provider.on_connect(&mut client)?;
+        provider.subscribe(&mut client, list_of_stocks)?;
+
+        let heartbeat = provider.heartbeat();
+
+        if heartbeat.is_some() {
+            set_read_timeout(&client, self.read_timeout);
+        }
+
+        let mut last_heartbeat = Instant::now();
+
+        loop {
+            if let Some((interval, message)) = &heartbeat {
+                if last_heartbeat.elapsed() >= *interval {
+                    let _ = client.send(message.clone());
+                    last_heartbeat = Instant::now();
+                }
+            }
+
+            let msg = match client.read() {
+                Ok(p) => p,
+                Err(Error::Io(ref error)) if error.kind() == ErrorKind::WouldBlock || error.kind() == ErrorKind::TimedOut => {
+                    continue;
+                },
+                Err(e) => {
+                    println!("Error receiving message {} \n Closing Client", e);
+                    let _ = client.send(Message::Close(None));
+                    break;
+                },
+            };
+
+            if let (Some(frame_recorder), Message::Text(text)) = (&self.frame_recorder, &msg) {
+                frame_recorder.record(text);
+            }
+
+            if !handle_message(provider, &mut client, msg) {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/*
+    Returns false once the session should be closed
+*/
+fn handle_message<P: MarketDataProvider>(provider: &mut P, client: &mut WsClient, msg: Message) -> bool {
+    match msg {
+        Message::Text(text) => {
+            let _ = provider.parse_frame(&text);
+            println!("{}", text);
+        }
+        Message::Close(_) => {
+            let _ = client.send(Message::Close(None));
+            return false;
+        }
+        Message::Ping(_) => {
+            println!("Received Ping. Sending Pong");
+            if client.send(Message::Pong(Vec::new())).is_err() {
+                return false;
+            }
+        }
+        _ => {
+            println!("Sending Ping");
+            if client.send(Message::Ping(Vec::new())).is_err() {
+                return false;
+            }
+        },
+    }
+
+    true
+}
+
+pub fn set_read_timeout(client: &WsClient, timeout: Duration) {
+    let _ = match client.get_ref() {
+        MaybeTlsStream::Plain(stream) => stream.set_read_timeout(Some(timeout)),
+        MaybeTlsStream::NativeTls(stream) => stream.get_ref().set_read_timeout(Some(timeout)),
+        _ => Ok(()),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    use tungstenite::{accept, Message, Error};
+
+    use crate::web_clients::market_data_provider::{MarketDataProvider, WsClient};
+    use crate::web_clients::session_driver::SessionDriver;
+
+    struct MockProvider {
+        url: String,
+        frames: Vec<String>,
+        heartbeat: Option<(Duration, Message)>,
+    }
+
+    impl MarketDataProvider for MockProvider {
+        fn name(&self) -> &str {
+            "mock"
+        }
+
+        fn url(&self) -> String {
+            self.url.clone()
+        }
+
+        fn subscribe(&mut self, client: &mut WsClient, list_of_stocks: &[String]) -> Result<(), Box<Error>> {
+            for stock in list_of_stocks.iter() {
+                client.send(Message::Text(format!("sub {}", stock)))?;
+            }
+
+            Ok(())
+        }
+
+        fn parse_frame(&mut self, text: &str) -> bool {
+            self.frames.push(text.to_string());
+            true
+        }
+
+        fn heartbeat(&self) -> Option<(Duration, Message)> {
+            self.heartbeat.clone()
+        }
+    }
+
+    fn mock_server<F>(handler: F) -> String where F: FnOnce(tungstenite::WebSocket<std::net::TcpStream>) + Send + 'static {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            handler(accept(stream).unwrap());
+        });
+
+        format!("ws://{}", addr)
+    }
+
+    #[test]
+    fn session_subscribes_and_forwards_frames() {
+        let (tx, rx) = mpsc::channel();
+
+        let url = mock_server(move |mut ws| {
+            let mut subscriptions = Vec::new();
+
+            for _ in 0..2 {
+                subscriptions.push(ws.read().unwrap().into_text().unwrap());
+            }
+
+            tx.send(subscriptions).unwrap();
+
+            ws.send(Message::Text("frame 1".to_string())).unwrap();
+            ws.send(Message::Ping(Vec::new())).unwrap();
+            ws.send(Message::Text("frame 2".to_string())).unwrap();
+            ws.close(None).unwrap();
+
+            while ws.read().is_ok() {}
+        });
+
+        let mut provider = MockProvider { url, frames: Vec::new(), heartbeat: None };
+        let stocks = vec!["AAPL".to_string(), "MSFT".to_string()];
+
+        SessionDriver::new().run_session(&mut provider, &stocks).unwrap();
+
+        assert_eq!(rx.recv().unwrap(), vec!["sub AAPL", "sub MSFT"]);
+        assert_eq!(provider.frames, vec!["frame 1", "frame 2"]);
+    }
+
+    #[test]
+    fn session_sends_heartbeat() {
+        let (tx, rx) = mpsc::channel();
+
+        let url = mock_server(move |mut ws| {
+            let msg = ws.read().unwrap();
+            tx.send(msg).unwrap();
+            ws.close(None).unwrap();
+
+            while ws.read().is_ok() {}
+        });
+
+        let mut provider = MockProvider {
+            url,
+            frames: Vec::new(),
+            heartbeat: Some((Duration::from_millis(10), Message::Text("beat".to_string()))),
+        };
+
+        SessionDriver::new().run_session(&mut provider, &[]).unwrap();
+
+        assert_eq!(rx.recv().unwrap(), Message::Text("beat".to_string()));
+    }
+
+    #[test]
+    fn session_reports_connection_errors() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let mut provider = MockProvider { url: format!("ws://{}", addr), frames: Vec::new(), heartbeat: None };
+
+        assert!(SessionDriver::new().run_session(&mut provider, &[]).is_err());
+    }
+}